@@ -1,84 +1,178 @@
-use regex::Regex;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::PathBuf;
 
-fn main() {
-    let file_name = PathBuf::from("./input/day4");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Range {
+    begin: u32,
+    end: u32,
+}
 
-    // first part
-    match read_lines(&file_name) {
-        Ok(lines) => {
-            let count = lines
-                .into_iter()
-                .flatten()
-                .flat_map(move |line| {
-                    let regex = Regex::new(r"(\d+)-(\d+)").unwrap();
-                    let numbers = regex
-                        .captures_iter(&line)
-                        .flat_map(move |entry| {
-                            let begin = entry[1].parse::<u32>().expect("");
-                            let end = entry[2].parse::<u32>().expect("");
-
-                            Some((begin, end))
-                        })
-                        .collect::<Vec<_>>();
-
-                    assert!(numbers.len() == 2);
-
-                    if numbers[0].0 >= numbers[1].0 && numbers[0].1 <= numbers[1].1
-                        || numbers[1].0 >= numbers[0].0 && numbers[1].1 <= numbers[0].1
-                    {
-                        Some(())
-                    } else {
-                        None
-                    }
-                })
-                .count();
-
-            println!("1. number of fully contained pairs: {count}");
+impl Range {
+    fn new(begin: u32, end: u32) -> Self {
+        Self { begin, end }
+    }
+
+    fn contains(&self, other: &Range) -> bool {
+        self.begin <= other.begin && other.end <= self.end
+    }
+
+    fn overlaps(&self, other: &Range) -> bool {
+        self.begin <= other.end && other.begin <= self.end
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseErrorKind {
+    ExpectedNumber,
+    NumberTooLarge,
+    Expected(char),
+    ReversedBounds(u32, u32),
+    TrailingInput,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            ParseErrorKind::ExpectedNumber => write!(f, "expected a number"),
+            ParseErrorKind::NumberTooLarge => write!(f, "number does not fit into u32"),
+            ParseErrorKind::Expected(c) => write!(f, "expected '{c}'"),
+            ParseErrorKind::ReversedBounds(begin, end) => {
+                write!(f, "reversed bounds {begin}-{end}")
+            }
+            ParseErrorKind::TrailingInput => write!(f, "unexpected trailing input"),
         }
-        Err(e) => {
-            panic!("Could not open file: {e}");
+    }
+}
+
+/// Hand-written tokenizer for lines of the form `a-b,c-d`.
+///
+/// Works directly on the bytes of the borrowed line, so parsing does not allocate.
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, line: usize) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+            line,
         }
     }
 
-    // second part
-    match read_lines(&file_name) {
+    fn error(&self, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: column + 1,
+            kind,
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, ParseError> {
+        let start = self.pos;
+        let mut value = 0_u32;
+
+        while let Some(digit) = self.input.get(self.pos).filter(|b| b.is_ascii_digit()) {
+            value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add((digit - b'0') as u32))
+                .ok_or_else(|| self.error(start, ParseErrorKind::NumberTooLarge))?;
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            return Err(self.error(start, ParseErrorKind::ExpectedNumber));
+        }
+
+        Ok(value)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ParseError> {
+        if self.input.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(self.pos, ParseErrorKind::Expected(c as char)))
+        }
+    }
+
+    fn range(&mut self) -> Result<Range, ParseError> {
+        let start = self.pos;
+        let begin = self.number()?;
+        self.expect(b'-')?;
+        let end = self.number()?;
+
+        if begin > end {
+            return Err(self.error(start, ParseErrorKind::ReversedBounds(begin, end)));
+        }
+
+        Ok(Range::new(begin, end))
+    }
+
+    fn pair(mut self) -> Result<(Range, Range), ParseError> {
+        let first = self.range()?;
+        self.expect(b',')?;
+        let second = self.range()?;
+
+        if self.pos != self.input.len() {
+            return Err(self.error(self.pos, ParseErrorKind::TrailingInput));
+        }
+
+        Ok((first, second))
+    }
+}
+
+fn parse_pairs(lines: &[String]) -> Result<Vec<(Range, Range)>, ParseError> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| Parser::new(line, i + 1).pair())
+        .collect()
+}
+
+fn main() {
+    let file_name = PathBuf::from("./input/day4");
+
+    let pairs = match read_lines(&file_name) {
         Ok(lines) => {
-            let count = lines
-                .into_iter()
-                .flatten()
-                .flat_map(move |line| {
-                    let regex = Regex::new(r"(\d+)-(\d+)").unwrap();
-                    let numbers = regex
-                        .captures_iter(&line)
-                        .flat_map(move |entry| {
-                            let begin = entry[1].parse::<u32>().expect("");
-                            let end = entry[2].parse::<u32>().expect("");
-
-                            Some((begin, end))
-                        })
-                        .collect::<Vec<_>>();
-
-                    if numbers[0].0 >= numbers[1].0 && numbers[0].0 <= numbers[1].1
-                        || numbers[0].1 >= numbers[1].0 && numbers[0].1 <= numbers[1].1
-                        || numbers[1].0 >= numbers[0].0 && numbers[1].0 <= numbers[0].1
-                        || numbers[1].1 >= numbers[0].0 && numbers[1].1 <= numbers[0].1
-                    {
-                        Some(())
-                    } else {
-                        None
-                    }
-                })
-                .count();
-
-            println!("2. number of partially contained pairs: {count}");
+            let lines = lines.map_while(Result::ok).collect::<Vec<_>>();
+            match parse_pairs(&lines) {
+                Ok(pairs) => pairs,
+                Err(e) => panic!("invalid input: {e}"),
+            }
         }
         Err(e) => {
             panic!("Could not open file: {e}");
         }
-    }
+    };
+
+    // first part
+    let count = pairs
+        .iter()
+        .filter(|(first, second)| first.contains(second) || second.contains(first))
+        .count();
+
+    println!("1. number of fully contained pairs: {count}");
+
+    // second part
+    let count = pairs
+        .iter()
+        .filter(|(first, second)| first.overlaps(second))
+        .count();
+
+    println!("2. number of partially contained pairs: {count}");
 }
 
 fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
@@ -86,3 +180,60 @@ fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
 
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> ParseError {
+        Parser::new(input, 1)
+            .pair()
+            .expect_err("input should be rejected")
+    }
+
+    #[test]
+    fn parses_pair() {
+        assert_eq!(
+            Parser::new("2-4,6-8", 1).pair(),
+            Ok((Range::new(2, 4), Range::new(6, 8)))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_comma() {
+        let e = error("2-4 6-8");
+        assert_eq!((e.column, e.kind), (4, ParseErrorKind::Expected(',')));
+    }
+
+    #[test]
+    fn rejects_reversed_bounds() {
+        let e = error("7-3,1-2");
+        assert_eq!(
+            (e.column, e.kind),
+            (1, ParseErrorKind::ReversedBounds(7, 3))
+        );
+
+        let e = error("1-2,7-3");
+        assert_eq!(
+            (e.column, e.kind),
+            (5, ParseErrorKind::ReversedBounds(7, 3))
+        );
+    }
+
+    #[test]
+    fn rejects_extra_fields() {
+        let e = error("2-4,6-8,1-2");
+        assert_eq!((e.column, e.kind), (8, ParseErrorKind::TrailingInput));
+    }
+
+    #[test]
+    fn reports_line_of_error() {
+        let lines = ["2-4,6-8", "2-3,4"].map(str::to_string);
+        let e = parse_pairs(&lines).expect_err("second line is invalid");
+        assert_eq!(e.to_string(), "line 2, column 6: expected '-'");
+        assert_eq!(
+            (e.line, e.column, e.kind),
+            (2, 6, ParseErrorKind::Expected('-'))
+        );
+    }
+}
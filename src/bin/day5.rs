@@ -1,21 +1,40 @@
+use std::env;
 use std::fmt::Display;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead};
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
 
 use itertools::Itertools;
 
+/// A crate with its label and a unique identity.
+///
+/// Labels may repeat, so `uid` is what tells crates apart while following them through the moves.
+/// Comparing crates only looks at their labels, since that is all a drawing shows.
 #[derive(Debug, Clone)]
 struct Crate {
-    id: char,
+    id: String,
+    uid: usize,
 }
 
 impl Crate {
-    fn new(id: char) -> Self {
-        Self { id }
+    fn new(id: String) -> Self {
+        Self { id, uid: 0 }
     }
 }
 
+impl PartialEq for Crate {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Crate {}
+
 impl Display for Crate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[{}]", self.id)
@@ -26,12 +45,35 @@ impl TryFrom<&str> for Crate {
     type Error = ();
 
     fn try_from(val: &str) -> Result<Self, Self::Error> {
-        if val.starts_with("[") && val.ends_with("]") && val.len() == 3 {
-            return Ok(Crate::new(val.chars().nth(1).expect("")));
+        match val.strip_prefix('[').and_then(|val| val.strip_suffix(']')) {
+            Some(id) if !id.is_empty() && !id.contains(['[', ']']) => {
+                Ok(Crate::new(id.to_string()))
+            }
+            _ => Err(()),
         }
+    }
+}
 
-        Err(())
+/// Position of a stack in the drawing, taken from its number in the last line.
+///
+/// Positions are doubled character offsets of the center, so that labels and numbers of even
+/// width can be centered without rounding.
+fn stack_positions(numbers: &str) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut start = None;
+
+    for (i, c) in numbers.chars().chain(std::iter::once(' ')).enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(from)) => {
+                positions.push(from + i - 1);
+                start = None;
+            }
+            _ => (),
+        }
     }
+
+    positions
 }
 
 #[derive(Debug)]
@@ -43,100 +85,644 @@ impl Row {
     fn new(crates: Vec<Option<Crate>>) -> Self {
         Self { crates }
     }
+
+    /// Parses a row of the drawing, assigning each `[label]` to the stack whose number is
+    /// closest to the center of the label.
+    fn parse(row: &str, positions: &[usize]) -> Self {
+        let mut crates = vec![None; positions.len()];
+        let chars = row.chars().collect_vec();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let end = match chars[i] {
+                '[' => chars[i..].iter().position(|c| *c == ']').map(|len| i + len),
+                _ => None,
+            };
+
+            match end {
+                Some(end) => {
+                    let label = chars[i..=end].iter().collect::<String>();
+                    let stack = positions
+                        .iter()
+                        .position_min_by_key(|pos| pos.abs_diff(i + end));
+
+                    if let (Ok(freight), Some(stack)) = (Crate::try_from(label.as_str()), stack) {
+                        crates[stack] = Some(freight);
+                    }
+                    i = end + 1;
+                }
+                None => i += 1,
+            }
+        }
+
+        Self::new(crates)
+    }
 }
 
-impl From<String> for Row {
-    fn from(row: String) -> Self {
-        let row = row
-            .chars()
-            .chunks(4)
-            .into_iter()
-            .map(move |chunk| {
-                let mut chars = chunk.collect::<String>();
-                chars.truncate(3);
-                chars
-            })
-            .map(move |chars| Crate::try_from(chars.as_str()))
-            .map(move |freight| match freight {
-                Ok(freight) => Some(freight),
-                Err(_) => None,
-            })
-            .collect_vec();
+/// Priority for a new treap node, pseudo random but deterministic (splitmix64 over a counter).
+fn next_priority() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut z = COUNTER
+        .fetch_add(1, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Node of an implicit treap, ordered by position instead of by key.
+///
+/// Nodes are shared between stacks and yards through `Rc` and copied on write. A `flipped` node
+/// stands for its subtree in reverse order, the flag is only pushed down to the children when
+/// the subtree has to be split or merged.
+#[derive(Debug, Clone)]
+struct Node {
+    freight: Crate,
+    priority: u64,
+    size: usize,
+    flipped: bool,
+    left: Option<Rc<Node>>,
+    right: Option<Rc<Node>>,
+}
+
+type Tree = Option<Rc<Node>>;
+
+fn size(tree: &Tree) -> usize {
+    tree.as_ref().map_or(0, |node| node.size)
+}
 
-        Self::new(row)
+fn flip(tree: &mut Tree) {
+    if let Some(node) = tree {
+        let node = Rc::make_mut(node);
+        node.flipped = !node.flipped;
     }
 }
 
-#[derive(Debug)]
-struct Storage {
-    pub stacks: Vec<Vec<Crate>>,
+/// Resolves a pending reversal of the root, so its children are in their real order.
+fn push_down(node: &mut Rc<Node>) -> &mut Node {
+    let node = Rc::make_mut(node);
+    if node.flipped {
+        node.flipped = false;
+        std::mem::swap(&mut node.left, &mut node.right);
+        flip(&mut node.left);
+        flip(&mut node.right);
+    }
+    node
 }
 
-impl Storage {
-    fn new(stacks: Vec<Vec<Crate>>) -> Self {
-        Self { stacks }
+fn update(node: &mut Node) {
+    node.size = 1 + size(&node.left) + size(&node.right);
+}
+
+/// Splits a tree into its bottom `cnt` crates and the rest, in expected O(log n).
+fn split(tree: Tree, cnt: usize) -> (Tree, Tree) {
+    let mut root = match tree {
+        Some(root) => root,
+        None => return (None, None),
+    };
+
+    let node = push_down(&mut root);
+    if size(&node.left) >= cnt {
+        let (low, high) = split(node.left.take(), cnt);
+        node.left = high;
+        update(node);
+        (low, Some(root))
+    } else {
+        let (low, high) = split(node.right.take(), cnt - size(&node.left) - 1);
+        node.right = low;
+        update(node);
+        (Some(root), high)
     }
+}
 
-    fn apply(&mut self, m: Move) {
-        for _ in 0..m.cnt {
-            let item = self.stacks[m.from_idx as usize - 1]
-                .pop()
-                .expect("invalid move!");
-            self.stacks[m.to_idx as usize - 1].push(item);
+/// Concatenates two trees, `high` ending up on top of `low`, in expected O(log n).
+fn merge(low: Tree, high: Tree) -> Tree {
+    match (low, high) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut low), Some(mut high)) => {
+            if low.priority > high.priority {
+                let node = push_down(&mut low);
+                node.right = merge(node.right.take(), Some(high));
+                update(node);
+                Some(low)
+            } else {
+                let node = push_down(&mut high);
+                node.left = merge(Some(low), node.left.take());
+                update(node);
+                Some(high)
+            }
         }
     }
+}
+
+/// Iterates over a tree without resolving pending reversals, in either direction.
+struct Iter<'a> {
+    // nodes still to visit, each with whether its subtree is seen in reverse
+    pending: Vec<(&'a Node, bool)>,
+    remaining: usize,
+}
 
-    fn apply_v2(&mut self, m: Move) {
-        let len = self.stacks[m.from_idx as usize - 1].len();
-        let items = self.stacks[m.from_idx as usize - 1].split_off(len - m.cnt as usize);
-        self.stacks[m.to_idx as usize - 1].extend_from_slice(&items);
+impl<'a> Iter<'a> {
+    fn new(tree: &'a Tree, from_top: bool) -> Self {
+        let mut iter = Self {
+            pending: Vec::new(),
+            remaining: size(tree),
+        };
+        iter.descend(tree.as_deref(), from_top);
+        iter
     }
 
-    fn get_top(&self) -> String {
-        self.stacks
-            .iter()
-            .map(|stack| stack.last().expect("invalid state").id.clone())
-            .collect::<String>()
+    /// Pushes the path to the first crate of the subtree in iteration order.
+    fn descend(&mut self, mut node: Option<&'a Node>, mut reversed: bool) {
+        while let Some(cur) = node {
+            reversed ^= cur.flipped;
+            self.pending.push((cur, reversed));
+            node = if reversed { &cur.right } else { &cur.left }.as_deref();
+        }
     }
 }
 
-impl From<Vec<Row>> for Storage {
-    fn from(rows: Vec<Row>) -> Self {
-        let mut stacks: Vec<Vec<Crate>> = vec![Vec::new(); rows[0].crates.len()];
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Crate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, reversed) = self.pending.pop()?;
+        let next = if reversed { &node.left } else { &node.right };
+        self.descend(next.as_deref(), reversed);
+        self.remaining -= 1;
+        Some(&node.freight)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// A stack of crates stored as an implicit treap, so a move of any number of crates splits one
+/// stack and joins another in expected O(log n), and reversing the lifted crates is O(1).
+#[derive(Debug, Clone, Default)]
+struct Stack {
+    root: Tree,
+}
+
+impl Stack {
+    fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    /// The crates of the stack, bottom to top.
+    fn iter(&self) -> Iter<'_> {
+        Iter::new(&self.root, false)
+    }
+
+    /// The crates of the stack, top to bottom.
+    fn iter_top(&self) -> Iter<'_> {
+        Iter::new(&self.root, true)
+    }
+
+    fn last(&self) -> Option<&Crate> {
+        self.iter_top().next()
+    }
+
+    /// Removes the top `cnt` crates.
+    fn take_top(&mut self, cnt: usize) -> Stack {
+        let len = self.len();
+        assert!(cnt <= len, "not enough crates");
+
+        let (low, high) = split(self.root.take(), len - cnt);
+        self.root = low;
+        Stack { root: high }
+    }
+
+    /// Turns the stack upside down.
+    fn reverse(&mut self) {
+        flip(&mut self.root);
+    }
+
+    /// Places another stack on top of this one.
+    fn put(&mut self, other: Stack) {
+        self.root = merge(self.root.take(), other.root);
+    }
+}
+
+impl From<Vec<Crate>> for Stack {
+    fn from(crates: Vec<Crate>) -> Self {
+        let root = crates.into_iter().fold(None, |root, freight| {
+            let node = Node {
+                freight,
+                priority: next_priority(),
+                size: 1,
+                flipped: false,
+                left: None,
+                right: None,
+            };
+            merge(root, Some(Rc::new(node)))
+        });
+
+        Self { root }
+    }
+}
+
+impl PartialEq for Stack {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Stack {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Storage {
+    pub stacks: Vec<Stack>,
+}
+
+impl Storage {
+    fn new(stacks: Vec<Stack>) -> Self {
+        Self { stacks }
+    }
+
+    fn from_rows(num_stacks: usize, rows: Vec<Row>) -> Self {
+        let mut stacks: Vec<Vec<Crate>> = vec![Vec::new(); num_stacks];
+        let mut uid = 0;
 
         rows.into_iter().for_each(|row| {
             row.crates.into_iter().enumerate().for_each(|(i, freight)| {
-                if let Some(freight) = freight {
+                if let Some(mut freight) = freight {
+                    freight.uid = uid;
+                    uid += 1;
                     stacks[i].push(freight);
                 }
             });
         });
 
-        Storage::new(stacks)
+        Storage::new(stacks.into_iter().map(Stack::from).collect_vec())
+    }
+
+    /// Moves the top `cnt` crates of one stack onto another in a single lift.
+    ///
+    /// With `keep_order` the crates keep their order, otherwise they end up reversed as if they
+    /// had been moved one at a time.
+    fn lift(&mut self, from_idx: usize, to_idx: usize, cnt: usize, keep_order: bool) {
+        // putting crates back onto the stack they came from leaves it as it was, like moving
+        // them one at a time does
+        if from_idx == to_idx {
+            return;
+        }
+
+        let mut items = self.stacks[from_idx - 1].take_top(cnt);
+        if !keep_order {
+            items.reverse();
+        }
+        self.stacks[to_idx - 1].put(items);
     }
-}
 
-impl From<Vec<String>> for Storage {
-    fn from(input: Vec<String>) -> Self {
-        let rows = input
+    /// The id of the topmost crate of every stack, `None` for empty stacks.
+    fn get_top(&self) -> Vec<Option<&str>> {
+        self.stacks
+            .iter()
+            .map(|stack| stack.last().map(|freight| freight.id.as_str()))
+            .collect_vec()
+    }
+
+    /// The top row as a string, with `_` standing in for empty stacks.
+    fn top_row(&self) -> String {
+        self.get_top()
             .into_iter()
-            .skip(1)
-            .map(move |row| Row::from(row))
+            .map(|id| id.unwrap_or("_"))
+            .collect::<String>()
+    }
+
+    /// Width of a single column of the drawing, wide enough for every label and stack number.
+    fn column_width(&self) -> usize {
+        self.stacks
+            .iter()
+            .flat_map(Stack::iter)
+            .map(|freight| freight.id.chars().count() + 2)
+            .chain(std::iter::once(self.stacks.len().to_string().len()))
+            .max()
+            .unwrap_or(0)
+            .max(3)
+    }
+
+    /// Serializes the yard as `{"stacks": [["A", "B"], ...]}`, each stack listed bottom to top.
+    fn to_json(&self) -> String {
+        let stacks = self
+            .stacks
+            .iter()
+            .map(|stack| {
+                let crates = stack
+                    .iter()
+                    .map(|freight| json_string(&freight.id))
+                    .join(", ");
+                format!("[{crates}]")
+            })
+            .join(", ");
+
+        format!("{{\"stacks\": [{stacks}]}}")
+    }
+
+    /// Reads the JSON written by `to_json`, numbering the crates like a parsed drawing.
+    fn from_json(json: &str) -> Result<Self, String> {
+        let mut reader = JsonReader::new(json);
+
+        reader.expect('{')?;
+        match reader.string()?.as_str() {
+            "stacks" => reader.expect(':')?,
+            key => return Err(format!("unknown key `{key}`")),
+        }
+        let stacks = reader.list(|reader| reader.list(JsonReader::string))?;
+        reader.expect('}')?;
+        reader.end()?;
+
+        let stacks = stacks
+            .into_iter()
+            .map(|stack| {
+                stack
+                    .into_iter()
+                    .map(|id| {
+                        Crate::try_from(format!("[{id}]").as_str())
+                            .map_err(|_| format!("invalid crate label `{id}`"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let height = stacks.iter().map(Vec::len).max().unwrap_or(0);
+        let rows = (0..height)
+            .map(|level| {
+                Row::new(
+                    stacks
+                        .iter()
+                        .map(|stack| stack.get(level).cloned())
+                        .collect(),
+                )
+            })
             .collect_vec();
 
-        Storage::from(rows)
+        Ok(Storage::from_rows(stacks.len(), rows))
+    }
+
+    fn diff(&self, other: &Storage) -> Vec<StackDiff> {
+        (0..self.stacks.len().max(other.stacks.len()))
+            .flat_map(|i| {
+                let old = self.stacks.get(i).map(|stack| stack.iter().collect_vec());
+                let new = other.stacks.get(i).map(|stack| stack.iter().collect_vec());
+                let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+                let common = old
+                    .iter()
+                    .zip(new.iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if common == old.len() && common == new.len() {
+                    None
+                } else {
+                    Some(StackDiff {
+                        stack: i + 1,
+                        removed: old[common..].iter().map(|c| (*c).clone()).collect_vec(),
+                        added: new[common..].iter().map(|c| (*c).clone()).collect_vec(),
+                    })
+                }
+            })
+            .collect_vec()
+    }
+}
+
+fn json_string(val: &str) -> String {
+    let mut out = String::from('"');
+
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Reads the little JSON that `Storage::to_json` writes: objects, lists and strings.
+struct JsonReader<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> JsonReader<'a> {
+    fn new(json: &'a str) -> Self {
+        Self {
+            chars: json.char_indices().peekable(),
+        }
+    }
+
+    fn error(&mut self, expected: &str) -> String {
+        match self.chars.peek() {
+            Some((i, c)) => format!("expected {expected} at offset {i}, found `{c}`"),
+            None => format!("expected {expected}, found the end"),
+        }
+    }
+
+    /// The next character that is not whitespace, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.chars.next();
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{expected}`")))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("the end")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+
+        loop {
+            let c = match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c @ ('"' | '\\' | '/'))) => c,
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'b')) => '\u{8}',
+                    Some((_, 'f')) => '\u{c}',
+                    Some((_, 'u')) => {
+                        let hex = (0..4).filter_map(|_| self.chars.next()).map(|(_, c)| c);
+                        let hex = hex.collect::<String>();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or(format!("invalid escape `\\u{hex}`"))?
+                    }
+                    _ => return Err(self.error("an escape")),
+                },
+                Some((_, c)) => c,
+                None => return Err(self.error("`\"`")),
+            };
+            out.push(c);
+        }
+    }
+
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+
+        self.expect('[')?;
+        if self.peek() == Some(']') {
+            self.chars.next();
+            return Ok(items);
+        }
+
+        loop {
+            items.push(item(self)?);
+            match self.peek() {
+                Some(',') => self.chars.next(),
+                Some(']') => {
+                    self.chars.next();
+                    return Ok(items);
+                }
+                _ => return Err(self.error("`,` or `]`")),
+            };
+        }
     }
 }
 
+/// Difference of a single stack between two yards, above the crates both have in common.
 #[derive(Debug)]
+struct StackDiff {
+    stack: usize,
+    removed: Vec<Crate>,
+    added: Vec<Crate>,
+}
+
+impl Display for StackDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stack {}: -{} +{}",
+            self.stack,
+            self.removed.iter().join(""),
+            self.added.iter().join("")
+        )
+    }
+}
+
+impl Display for Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let height = self
+            .stacks
+            .iter()
+            .map(|stack| stack.len())
+            .max()
+            .unwrap_or(0);
+
+        let width = self.column_width();
+        let stacks = self
+            .stacks
+            .iter()
+            .map(|stack| stack.iter().collect_vec())
+            .collect_vec();
+
+        for level in (0..height).rev() {
+            let row = stacks
+                .iter()
+                .map(|stack| match stack.get(level) {
+                    Some(freight) => format!("{:^width$}", freight.to_string()),
+                    None => " ".repeat(width),
+                })
+                .join(" ");
+            writeln!(f, "{}", row.trim_end())?;
+        }
+
+        let numbers = (1..=self.stacks.len())
+            .map(|i| format!("{i:^width$}"))
+            .join(" ");
+        write!(f, "{}", numbers.trim_end())
+    }
+}
+
+impl From<Vec<Row>> for Storage {
+    fn from(rows: Vec<Row>) -> Self {
+        let num_stacks = rows.iter().map(|row| row.crates.len()).max().unwrap_or(0);
+
+        Storage::from_rows(num_stacks, rows)
+    }
+}
+
+/// Expects the lines of the drawing bottom up, starting with the line of stack numbers.
+///
+/// The stack numbers have to be `1` to `n`, a drawing without them would otherwise silently
+/// lose its bottom row.
+impl TryFrom<Vec<String>> for Storage {
+    type Error = ParseError;
+
+    fn try_from(input: Vec<String>) -> Result<Self, Self::Error> {
+        // line of the stack numbers, counted from the top of the drawing
+        let line = input.len().max(1);
+        let mut input = input.into_iter();
+        let numbers = input.next().unwrap_or_default();
+
+        let mut stacks = numbers.split_whitespace().peekable();
+        if stacks.peek().is_none() || !(1..).zip(stacks).all(|(i, n)| n == i.to_string()) {
+            return Err(ParseError {
+                line,
+                kind: ParseErrorKind::InvalidNumbering(numbers),
+            });
+        }
+
+        let positions = stack_positions(&numbers);
+        let rows = input.map(|row| Row::parse(&row, &positions)).collect_vec();
+
+        Ok(Storage::from_rows(positions.len(), rows))
+    }
+}
+
+/// Parses the drawing as it appears in the input, i.e. top down.
+impl FromStr for Storage {
+    type Err = ParseError;
+
+    fn from_str(drawing: &str) -> Result<Self, Self::Err> {
+        let lines = drawing.lines().rev().map(str::to_string).collect_vec();
+
+        Storage::try_from(lines)
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Move {
-    cnt: u8,
-    from_idx: u8,
-    to_idx: u8,
+    cnt: usize,
+    from_idx: usize,
+    to_idx: usize,
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.cnt, self.from_idx, self.to_idx
+        )
+    }
 }
 
 impl Move {
-    fn new(cnt: u8, from_idx: u8, to_idx: u8) -> Self {
+    fn new(cnt: usize, from_idx: usize, to_idx: usize) -> Self {
         Self {
             cnt,
             from_idx,
@@ -145,88 +731,764 @@ impl Move {
     }
 }
 
-impl From<String> for Move {
-    fn from(line: String) -> Self {
-        let mut words = line.split_whitespace();
+/// Parses `move <cnt> from <from> to <to>`.
+impl FromStr for Move {
+    type Err = ();
 
-        assert_eq!(words.next().expect("invalid input"), "move");
-        let cnt = words
-            .next()
-            .expect("invalid input")
-            .parse::<u8>()
-            .expect("invalid input");
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let num = |val: &str| val.parse::<usize>().map_err(|_| ());
 
-        assert_eq!(words.next().expect("invalid input"), "from");
-        let from_idx = words
-            .next()
-            .expect("invalid_input")
-            .parse::<u8>()
-            .expect("invalid input");
+        match line.split_whitespace().collect_vec().as_slice() {
+            ["move", cnt, "from", from_idx, "to", to_idx] => {
+                Ok(Move::new(num(cnt)?, num(from_idx)?, num(to_idx)?))
+            }
+            _ => Err(()),
+        }
+    }
+}
 
-        assert_eq!(words.next().expect("invalid input"), "to");
-        let to_idx = words
-            .next()
-            .expect("invalid_input")
-            .parse::<u8>()
-            .expect("invalid input");
+#[derive(Debug, PartialEq, Eq)]
+enum ParseErrorKind {
+    InvalidNumbering(String),
+    InvalidMove(String),
+}
 
-        Move::new(cnt, from_idx, to_idx)
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::InvalidNumbering(line) => {
+                write!(f, "expected the stack numbers 1 to n, found `{line}`")
+            }
+            ParseErrorKind::InvalidMove(line) => write!(f, "invalid move `{line}`"),
+        }
     }
 }
 
-fn main() {
-    let file_name = PathBuf::from("./input/day5");
+#[derive(Debug)]
+enum MoveError {
+    NoSuchStack(usize),
+    NotEnoughCrates { stack: usize, available: usize },
+}
 
-    // task 1
-    match read_lines(&file_name) {
-        Ok(mut lines) => {
-            let mut input: Vec<String> = lines
-                .by_ref()
-                .take_while(|ref line| match line {
-                    Ok(line) => !line.is_empty(),
-                    Err(e) => panic!("{e}"),
-                })
-                .map(|line| line.as_ref().expect("").clone())
-                .collect();
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchStack(stack) => write!(f, "there is no stack {stack}"),
+            Self::NotEnoughCrates { stack, available } => {
+                write!(f, "stack {stack} only holds {available} crates")
+            }
+        }
+    }
+}
+
+impl Move {
+    /// The move taking the crates back where they came from.
+    fn inverse(&self) -> Move {
+        Move::new(self.cnt, self.to_idx, self.from_idx)
+    }
+
+    /// Checks that the move can be executed in the given yard by any crane.
+    fn check(&self, storage: &Storage) -> Result<(), MoveError> {
+        let stack = |idx: usize| {
+            idx.checked_sub(1)
+                .and_then(|i| storage.stacks.get(i))
+                .ok_or(MoveError::NoSuchStack(idx))
+        };
+
+        let from = stack(self.from_idx)?;
+        stack(self.to_idx)?;
+
+        if from.len() < self.cnt {
+            return Err(MoveError::NotEnoughCrates {
+                stack: self.from_idx,
+                available: from.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The first move of a procedure that could not be executed, together with the yard it failed on.
+#[derive(Debug)]
+struct IllegalMove {
+    line: usize,
+    step: usize,
+    m: Move,
+    error: MoveError,
+    state: String,
+}
 
-            input.reverse();
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "line {} (step {}): `{}`: {}",
+            self.line, self.step, self.m, self.error
+        )?;
+        write!(f, "{}", self.state)
+    }
+}
+
+/// Executes the `step`-th move (counting from 1) after checking that it is legal.
+fn execute_checked(
+    crane: &mut dyn Crane,
+    state: &mut Storage,
+    m: &Move,
+    step: usize,
+    first_line: usize,
+) -> Result<usize, IllegalMove> {
+    m.check(state).map_err(|error| IllegalMove {
+        line: first_line + step - 1,
+        step,
+        m: m.clone(),
+        error,
+        state: state.to_string(),
+    })?;
+
+    Ok(crane.execute(state, m))
+}
+
+trait Crane {
+    /// Executes a single move, returning the number of lifts it took.
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize;
+
+    /// Reverts a move this crane executed, or returns `None` if the crane cannot be reversed.
+    fn undo(&mut self, _storage: &mut Storage, _m: &Move) -> Option<usize> {
+        None
+    }
+
+    /// Summary printed once all moves have been executed.
+    fn report(&self) -> Option<String> {
+        None
+    }
+}
+
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize {
+        storage.lift(m.from_idx, m.to_idx, m.cnt, false);
+        m.cnt
+    }
+
+    fn undo(&mut self, storage: &mut Storage, m: &Move) -> Option<usize> {
+        Some(self.execute(storage, &m.inverse()))
+    }
+}
 
-            let mut state = Storage::from(input);
+struct CrateMover9001;
 
-            lines
+impl Crane for CrateMover9001 {
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize {
+        storage.lift(m.from_idx, m.to_idx, m.cnt, true);
+        1
+    }
+
+    fn undo(&mut self, storage: &mut Storage, m: &Move) -> Option<usize> {
+        Some(self.execute(storage, &m.inverse()))
+    }
+}
+
+/// Lifts at most `capacity` crates at once, keeping their order within a lift.
+struct CapacityCrane {
+    capacity: usize,
+}
+
+impl Crane for CapacityCrane {
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize {
+        let mut remaining = m.cnt;
+        let mut lifts = 0;
+
+        while remaining > 0 {
+            let cnt = remaining.min(self.capacity);
+            storage.lift(m.from_idx, m.to_idx, cnt, true);
+            remaining -= cnt;
+            lifts += 1;
+        }
+
+        lifts
+    }
+}
+
+/// Lifts all crates at once, but every other lift turns them upside down.
+#[derive(Default)]
+struct AlternatingCrane {
+    lifts: usize,
+}
+
+impl Crane for AlternatingCrane {
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize {
+        let keep_order = self.lifts.is_multiple_of(2);
+        storage.lift(m.from_idx, m.to_idx, m.cnt, keep_order);
+        self.lifts += 1;
+        1
+    }
+}
+
+/// Wraps another crane and counts the moves and lifts it performs.
+struct LoggingCrane {
+    inner: Box<dyn Crane>,
+    moves: usize,
+    lifts: usize,
+}
+
+impl Crane for LoggingCrane {
+    fn execute(&mut self, storage: &mut Storage, m: &Move) -> usize {
+        let lifts = self.inner.execute(storage, m);
+        self.moves += 1;
+        self.lifts += lifts;
+        lifts
+    }
+
+    fn undo(&mut self, storage: &mut Storage, m: &Move) -> Option<usize> {
+        let lifts = self.inner.undo(storage, m)?;
+        self.moves += 1;
+        self.lifts += lifts;
+        Some(lifts)
+    }
+
+    fn report(&self) -> Option<String> {
+        Some(format!("{} lifts for {} moves", self.lifts, self.moves))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CraneModel {
+    Mover9000,
+    Mover9001,
+    Capacity(usize),
+    Alternating,
+    Logging(Box<CraneModel>),
+}
+
+impl CraneModel {
+    fn build(&self) -> Box<dyn Crane> {
+        match self {
+            Self::Mover9000 => Box::new(CrateMover9000),
+            Self::Mover9001 => Box::new(CrateMover9001),
+            Self::Capacity(capacity) => Box::new(CapacityCrane {
+                capacity: *capacity,
+            }),
+            Self::Alternating => Box::<AlternatingCrane>::default(),
+            Self::Logging(inner) => Box::new(LoggingCrane {
+                inner: inner.build(),
+                moves: 0,
+                lifts: 0,
+            }),
+        }
+    }
+}
+
+impl Display for CraneModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mover9000 => write!(f, "9000"),
+            Self::Mover9001 => write!(f, "9001"),
+            Self::Capacity(capacity) => write!(f, "capacity:{capacity}"),
+            Self::Alternating => write!(f, "alternating"),
+            Self::Logging(inner) => write!(f, "logging:{inner}"),
+        }
+    }
+}
+
+/// Parses `9000`, `9001`, `capacity:<k>`, `alternating` or `logging:<model>`.
+impl FromStr for CraneModel {
+    type Err = String;
+
+    fn from_str(model: &str) -> Result<Self, Self::Err> {
+        match model.split_once(':') {
+            None if model == "9000" => Ok(Self::Mover9000),
+            None if model == "9001" => Ok(Self::Mover9001),
+            None if model == "alternating" => Ok(Self::Alternating),
+            Some(("capacity", capacity)) => match capacity.parse::<usize>() {
+                Ok(0) => Err("crane capacity has to be at least 1".to_string()),
+                Ok(capacity) => Ok(Self::Capacity(capacity)),
+                Err(e) => Err(format!("invalid crane capacity: {e}")),
+            },
+            Some(("logging", inner)) => Ok(Self::Logging(Box::new(inner.parse()?))),
+            _ => Err(format!("unknown crane model: {model}")),
+        }
+    }
+}
+
+/// Position of a crate after a step of the procedure, step 0 being the starting yard.
+#[derive(Debug, Clone, Copy)]
+struct Visit {
+    step: usize,
+    stack: usize,
+    height: usize,
+}
+
+/// History of every crate's positions, indexed by the crate's `uid`.
+#[derive(Debug)]
+struct Provenance {
+    labels: Vec<String>,
+    history: Vec<Vec<Visit>>,
+}
+
+impl Provenance {
+    fn new(storage: &Storage) -> Self {
+        let mut crates = storage
+            .stacks
+            .iter()
+            .enumerate()
+            .flat_map(|(i, stack)| {
+                stack.iter().enumerate().map(move |(height, freight)| {
+                    let visit = Visit {
+                        step: 0,
+                        stack: i + 1,
+                        height: height + 1,
+                    };
+                    (freight.uid, freight.id.clone(), visit)
+                })
+            })
+            .collect_vec();
+        crates.sort_by_key(|(uid, _, _)| *uid);
+
+        Self {
+            labels: crates.iter().map(|(_, id, _)| id.clone()).collect_vec(),
+            history: crates
                 .into_iter()
-                .map(|line| Move::from(line.expect("invalid input")))
-                .for_each(|m| state.apply(m));
+                .map(|(_, _, visit)| vec![visit])
+                .collect_vec(),
+        }
+    }
 
-            println!("1. Top row: {}", state.get_top());
+    /// Records the new positions of the crates that the `step`-th move put on top of its target.
+    fn record(&mut self, storage: &Storage, step: usize, m: &Move) {
+        let stack = &storage.stacks[m.to_idx - 1];
+
+        for (depth, freight) in stack.iter_top().take(m.cnt).enumerate() {
+            self.history[freight.uid].push(Visit {
+                step,
+                stack: m.to_idx,
+                height: stack.len() - depth,
+            });
         }
-        Err(e) => {
-            panic!("Error reading files {e}");
+    }
+}
+
+/// Follows all crates through the procedure and prints the path of those labelled `label`.
+fn trace(input: &Input, model: &CraneModel, label: &str) -> Result<(), IllegalMove> {
+    let (mut state, moves, first_line) = (input.start.clone(), &input.moves, input.first_line);
+    let mut crane = model.build();
+    let mut provenance = Provenance::new(&state);
+
+    for (i, m) in moves.iter().enumerate() {
+        execute_checked(crane.as_mut(), &mut state, m, i + 1, first_line)?;
+        provenance.record(&state, i + 1, m);
+    }
+
+    let tracked = provenance
+        .labels
+        .iter()
+        .positions(|id| id == label)
+        .collect_vec();
+    if tracked.is_empty() {
+        println!("there is no crate [{label}]");
+    }
+
+    for uid in tracked {
+        println!("crate [{label}] #{uid} with crane {model}:");
+        for visit in &provenance.history[uid] {
+            match visit.step {
+                0 => print!("  start"),
+                step => print!(
+                    "  step {step} (line {}) `{}`",
+                    first_line + step - 1,
+                    moves[step - 1]
+                ),
+            }
+            println!(": stack {}, height {}", visit.stack, visit.height);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Options {
+    visualize: bool,
+    delay: Duration,
+    start_step: usize,
+    dump_step: Option<usize>,
+    json: bool,
+    diff: Option<(usize, usize)>,
+    diff_files: Option<(PathBuf, PathBuf)>,
+    cranes: Vec<CraneModel>,
+    end_file: Option<PathBuf>,
+    check_files: Option<(PathBuf, PathBuf)>,
+    track: Vec<String>,
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            visualize: false,
+            delay: Duration::from_millis(250),
+            start_step: 0,
+            dump_step: None,
+            json: false,
+            diff: None,
+            diff_files: None,
+            cranes: Vec::new(),
+            end_file: None,
+            check_files: None,
+            track: Vec::new(),
+        };
+
+        fn step(value: Option<String>, arg: &str) -> Result<usize, String> {
+            value
+                .ok_or(format!("{arg} expects a move number"))?
+                .parse::<usize>()
+                .map_err(|e| format!("invalid step for {arg}: {e}"))
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--visualize" => options.visualize = true,
+                "--delay" => {
+                    let millis = args
+                        .next()
+                        .ok_or("--delay expects a value in milliseconds")?
+                        .parse::<u64>()
+                        .map_err(|e| format!("invalid delay: {e}"))?;
+                    options.delay = Duration::from_millis(millis);
+                }
+                "--step" => options.start_step = step(args.next(), "--step")?,
+                "--dump" => options.dump_step = Some(step(args.next(), "--dump")?),
+                "--json" => options.json = true,
+                "--crane" => options.cranes.push(
+                    args.next()
+                        .ok_or("--crane expects a crane model")?
+                        .parse::<CraneModel>()?,
+                ),
+                "--diff" => {
+                    let from = step(args.next(), "--diff")?;
+                    let to = step(args.next(), "--diff")?;
+                    options.diff = Some((from, to));
+                }
+                "--diff-files" => {
+                    let old = args.next().ok_or("--diff-files expects two yard files")?;
+                    let new = args.next().ok_or("--diff-files expects two yard files")?;
+                    options.diff_files = Some((PathBuf::from(old), PathBuf::from(new)));
+                }
+                "--track" => options
+                    .track
+                    .push(args.next().ok_or("--track expects a crate label")?),
+                "--solve-start" => {
+                    options.end_file = Some(PathBuf::from(
+                        args.next().ok_or("--solve-start expects a drawing file")?,
+                    ));
+                }
+                "--check" => {
+                    let start = args.next().ok_or("--check expects two drawing files")?;
+                    let end = args.next().ok_or("--check expects two drawing files")?;
+                    options.check_files = Some((PathBuf::from(start), PathBuf::from(end)));
+                }
+                a => return Err(format!("unknown argument: {a}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Applies all moves to the state, printing the yard after each one.
+///
+/// The first `options.start_step` moves are applied without output, so the animation starts at
+/// that step.
+fn visualize(
+    state: &mut Storage,
+    moves: &[Move],
+    first_line: usize,
+    crane: &mut dyn Crane,
+    options: &Options,
+) -> Result<(), IllegalMove> {
+    let total = moves.len();
+    let start = options.start_step.min(total);
+
+    for (i, m) in moves.iter().enumerate().take(start) {
+        execute_checked(crane, state, m, i + 1, first_line)?;
+    }
+    print!("\x1b[2J\x1b[H");
+    println!("step {start}/{total}\n{state}\n");
+
+    for (i, m) in moves.iter().enumerate().skip(start) {
+        execute_checked(crane, state, m, i + 1, first_line)?;
+
+        thread::sleep(options.delay);
+        print!("\x1b[2J\x1b[H");
+        println!("step {}/{total}: {m}\n{state}\n", i + 1);
+    }
+
+    Ok(())
+}
+
+/// The starting yard and the moves of the procedure.
+#[derive(Debug, Clone)]
+struct Input {
+    start: Storage,
+    moves: Vec<Move>,
+    // line number of the first move, moves are on consecutive lines
+    first_line: usize,
+}
+
+/// Splits the input into the yard and the moves, ignoring blank lines at the end.
+fn parse_input(lines: &[String]) -> Result<Input, ParseError> {
+    let drawing_len = lines
+        .iter()
+        .position(|line| line.is_empty())
+        .unwrap_or(lines.len());
+    let drawing = lines[..drawing_len].iter().rev().cloned().collect_vec();
+    let first_line = drawing_len + 2;
+
+    let procedure = lines.get(drawing_len + 1..).unwrap_or_default();
+    let end = procedure
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |last| last + 1);
+    let moves = procedure[..end]
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            line.parse::<Move>().map_err(|_| ParseError {
+                line: first_line + i,
+                kind: ParseErrorKind::InvalidMove(line.clone()),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Input {
+        start: Storage::try_from(drawing)?,
+        moves,
+        first_line,
+    })
+}
+
+fn state_after(input: &Input, model: &CraneModel, step: usize) -> Result<Storage, IllegalMove> {
+    let mut state = input.start.clone();
+    let mut crane = model.build();
+
+    for (i, m) in input.moves.iter().enumerate().take(step) {
+        execute_checked(crane.as_mut(), &mut state, m, i + 1, input.first_line)?;
+    }
+
+    Ok(state)
+}
+
+/// Prints the intermediate states requested with `--dump` and `--diff` and the crates followed
+/// with `--track`.
+fn inspect(input: &Input, model: &CraneModel, options: &Options) -> Result<(), IllegalMove> {
+    if let Some(step) = options.dump_step {
+        let state = state_after(input, model, step)?;
+        println!("crane {model} after step {step}:");
+        if options.json {
+            println!("{}", state.to_json());
+        } else {
+            println!("{state}");
         }
     }
 
-    // task 2
+    if let Some((from, to)) = options.diff {
+        let old = state_after(input, model, from)?;
+        let new = state_after(input, model, to)?;
+
+        println!("crane {model}: diff between step {from} and {to}:");
+        old.diff(&new).iter().for_each(|diff| println!("{diff}"));
+    }
+
+    for label in &options.track {
+        trace(input, model, label)?;
+    }
+
+    Ok(())
+}
+
+fn run(input: &Input, model: &CraneModel, options: &Options) -> Result<Storage, IllegalMove> {
+    inspect(input, model, options)?;
+
+    let (mut state, moves, first_line) = (input.start.clone(), &input.moves, input.first_line);
+    let mut crane = model.build();
+
+    if options.visualize {
+        visualize(&mut state, moves, first_line, crane.as_mut(), options)?;
+    } else {
+        for (i, m) in moves.iter().enumerate() {
+            execute_checked(crane.as_mut(), &mut state, m, i + 1, first_line)?;
+        }
+    }
+
+    if let Some(report) = crane.report() {
+        println!("crane {model}: {report}");
+    }
+
+    Ok(state)
+}
+
+/// Recovers the yard before the moves from the yard after them, by undoing the moves in reverse
+/// order. As every undo is deterministic, the recovered start is the only one leading to `end`.
+fn solve_start(
+    end: &Storage,
+    moves: &[Move],
+    first_line: usize,
+    model: &CraneModel,
+) -> Result<Storage, String> {
+    let mut state = end.clone();
+    let mut crane = model.build();
+
+    for (i, m) in moves.iter().enumerate().rev() {
+        if let Err(error) = m.inverse().check(&state) {
+            let illegal = IllegalMove {
+                line: first_line + i,
+                step: i + 1,
+                m: m.clone(),
+                error,
+                state: state.to_string(),
+            };
+            return Err(format!("cannot undo {illegal}"));
+        }
+
+        crane
+            .undo(&mut state, m)
+            .ok_or(format!("crane {model} cannot undo moves"))?;
+    }
+
+    Ok(state)
+}
+
+/// Checks whether the moves take the crane from `start` to `end`, returning the stacks in which
+/// the actual end differs from the claimed one.
+fn check_pair(
+    start: &Storage,
+    end: &Storage,
+    moves: &[Move],
+    first_line: usize,
+    model: &CraneModel,
+) -> Result<Vec<StackDiff>, IllegalMove> {
+    let mut state = start.clone();
+    let mut crane = model.build();
+
+    for (i, m) in moves.iter().enumerate() {
+        execute_checked(crane.as_mut(), &mut state, m, i + 1, first_line)?;
+    }
+
+    Ok(end.diff(&state))
+}
+
+/// Reads a yard saved as a drawing or, if it starts with `{`, as JSON.
+fn read_drawing(name: &PathBuf) -> Result<Storage, String> {
+    let drawing = fs::read_to_string(name).map_err(|e| format!("{}: {}", name.display(), e))?;
+
+    let storage = if drawing.trim_start().starts_with('{') {
+        Storage::from_json(&drawing)
+    } else {
+        drawing
+            .trim_end_matches('\n')
+            .parse::<Storage>()
+            .map_err(|e| e.to_string())
+    };
+    storage.map_err(|e| format!("{}: {}", name.display(), e))
+}
+
+/// Runs the comparison of saved yards, the reverse solver and the consistency check requested
+/// on the command line.
+fn verify(input: &Input, models: &[CraneModel], options: &Options) -> Result<(), String> {
+    let (moves, first_line) = (&input.moves, input.first_line);
+
+    if let Some((old_file, new_file)) = &options.diff_files {
+        let old = read_drawing(old_file)?;
+        let new = read_drawing(new_file)?;
+
+        println!(
+            "diff between {} and {}:",
+            old_file.display(),
+            new_file.display()
+        );
+        old.diff(&new).iter().for_each(|diff| println!("{diff}"));
+    }
+
+    if let Some(end_file) = &options.end_file {
+        let end = read_drawing(end_file)?;
+
+        for model in models {
+            match solve_start(&end, moves, first_line, model) {
+                Ok(start) => println!("start for crane {model}:\n{start}"),
+                Err(e) => println!("no start for crane {model}: {e}"),
+            }
+        }
+    }
+
+    if let Some((start_file, end_file)) = &options.check_files {
+        let start = read_drawing(start_file)?;
+        let end = read_drawing(end_file)?;
+
+        for model in models {
+            match check_pair(&start, &end, moves, first_line, model) {
+                Ok(diffs) if diffs.is_empty() => println!("crane {model}: consistent"),
+                Ok(diffs) => {
+                    println!("crane {model}: inconsistent, the moves actually lead to");
+                    diffs.iter().for_each(|diff| println!("{diff}"));
+                }
+                Err(e) => println!("crane {model}: illegal move in {e}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let file_name = PathBuf::from("./input/day5");
+
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => panic!("{e}"),
+    };
+
     match read_lines(&file_name) {
-        Ok(mut lines) => {
-            let mut input: Vec<String> = lines
-                .by_ref()
-                .take_while(|ref line| match line {
-                    Ok(line) => !line.is_empty(),
-                    Err(e) => panic!("{e}"),
-                })
-                .map(|line| line.as_ref().expect("").clone())
-                .collect();
+        Ok(lines) => {
+            let lines = lines.map_while(Result::ok).collect_vec();
+            let input = match parse_input(&lines) {
+                Ok(input) => input,
+                Err(e) => panic!("Error in input: {e}"),
+            };
 
-            input.reverse();
+            // task 1
+            match run(&input, &CraneModel::Mover9000, &options) {
+                Ok(state) => println!("1. Top row: {}", state.top_row()),
+                Err(e) => println!("1. illegal move in {e}"),
+            }
 
-            let mut state = Storage::from(input);
+            // task 2
+            match run(&input, &CraneModel::Mover9001, &options) {
+                Ok(state) => println!("2. Top row with CrateMover 9001: {}", state.top_row()),
+                Err(e) => println!("2. illegal move in {e}"),
+            }
 
-            lines
-                .into_iter()
-                .map(|line| Move::from(line.expect("invalid input")))
-                .for_each(|m| state.apply_v2(m));
+            // additional cranes selected on the command line
+            for model in &options.cranes {
+                match run(&input, model, &options) {
+                    Ok(state) => println!("Top row with crane {model}: {}", state.top_row()),
+                    Err(e) => println!("illegal move for crane {model} in {e}"),
+                }
+            }
 
-            println!("2. Top row with CrateMover 9001: {}", state.get_top());
+            let models = [CraneModel::Mover9000, CraneModel::Mover9001]
+                .into_iter()
+                .chain(options.cranes.iter().cloned())
+                .collect_vec();
+            if let Err(e) = verify(&input, &models, &options) {
+                panic!("Error in drawing: {e}");
+            }
         }
         Err(e) => {
             panic!("Error reading files {e}");
@@ -239,3 +1501,358 @@ fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
 
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "    [D]
+[N] [C]
+[Z] [M] [P]
+ 1   2   3
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2";
+
+    fn example() -> Vec<String> {
+        EXAMPLE.lines().map(str::to_string).collect_vec()
+    }
+
+    /// Deterministic xorshift generator, enough to shuffle operations around.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    fn crates(range: std::ops::Range<usize>) -> Vec<Crate> {
+        range
+            .map(|uid| Crate {
+                id: uid.to_string(),
+                uid,
+            })
+            .collect_vec()
+    }
+
+    fn assert_stack(stack: &Stack, expected: &[Crate]) {
+        let uids = |crates: Vec<&Crate>| crates.iter().map(|c| c.uid).collect_vec();
+
+        assert_eq!(stack.len(), expected.len());
+        assert_eq!(
+            stack.iter().size_hint(),
+            (expected.len(), Some(expected.len()))
+        );
+        assert_eq!(
+            uids(stack.iter().collect_vec()),
+            uids(expected.iter().collect_vec())
+        );
+        assert_eq!(
+            uids(stack.iter_top().collect_vec()),
+            uids(expected.iter().rev().collect_vec())
+        );
+        assert_eq!(stack.last().map(|c| c.uid), expected.last().map(|c| c.uid));
+    }
+
+    #[test]
+    fn stacks_match_vec() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..50 {
+            let mut expected = Vec::new();
+            let mut stacks = Vec::new();
+            let mut next = 0;
+            for _ in 0..3 {
+                let len = random.below(20);
+                expected.push(crates(next..next + len));
+                stacks.push(Stack::from(crates(next..next + len)));
+                next += len;
+            }
+            let mut snapshots = Vec::new();
+
+            for _ in 0..200 {
+                let (from, to) = (random.below(3), random.below(3));
+
+                match random.below(4) {
+                    0 | 1 => {
+                        let cnt = random.below(expected[from].len() + 1);
+                        let reversed = random.below(2) == 0;
+
+                        let at = expected[from].len() - cnt;
+                        let mut lifted = expected[from].split_off(at);
+                        let mut items = stacks[from].take_top(cnt);
+                        assert_stack(&items, &lifted);
+                        if reversed {
+                            lifted.reverse();
+                            items.reverse();
+                        }
+                        expected[to].extend(lifted);
+                        stacks[to].put(items);
+                    }
+                    2 => {
+                        expected[from].reverse();
+                        stacks[from].reverse();
+                    }
+                    _ => snapshots.push((stacks.clone(), expected.clone())),
+                }
+
+                for (stack, expected) in stacks.iter().zip(&expected) {
+                    assert_stack(stack, expected);
+                }
+            }
+
+            // copies share their nodes, but none of the later changes may show up in them
+            for (stacks, expected) in snapshots {
+                for (stack, expected) in stacks.iter().zip(&expected) {
+                    assert_stack(stack, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cloned_storages_are_independent() {
+        let storage = parse_input(&example()).expect("valid input").start;
+        let mut copy = storage.clone();
+
+        copy.lift(2, 1, 3, false);
+        assert_eq!(storage.top_row(), "NDP");
+        assert_eq!(copy.top_row(), "M_P");
+
+        let mut original = storage.clone();
+        original.lift(1, 3, 2, true);
+        assert_eq!(original.top_row(), "_DN");
+        assert_eq!(copy.top_row(), "M_P");
+        assert_eq!(storage.top_row(), "NDP");
+    }
+
+    fn parse_error(text: &str) -> ParseError {
+        let lines = text.lines().map(str::to_string).collect_vec();
+        parse_input(&lines).expect_err("invalid input")
+    }
+
+    #[test]
+    fn ignores_trailing_blank_lines() {
+        let mut lines = example();
+        lines.extend(["".to_string(), "  ".to_string()]);
+        let input = parse_input(&lines).expect("valid input");
+
+        assert_eq!(input.moves.len(), 4);
+        assert_eq!(input.first_line, 6);
+    }
+
+    #[test]
+    fn rejects_invalid_moves() {
+        let invalid = |line: usize, text: &str| ParseError {
+            line,
+            kind: ParseErrorKind::InvalidMove(text.to_string()),
+        };
+
+        assert_eq!(
+            parse_error(&format!("{EXAMPLE}\nmove x from 1 to 2")),
+            invalid(10, "move x from 1 to 2")
+        );
+        assert_eq!(
+            parse_error(&format!("{EXAMPLE}\n\nmove 1 from 1 to 2")),
+            invalid(10, "")
+        );
+        assert_eq!(
+            parse_error(" 1\n\nmove 1 from 2\nmove 1 from 2 to 1"),
+            invalid(3, "move 1 from 2")
+        );
+        assert_eq!(
+            parse_error(" 1\n\nmove 1 from 2 to 1 now").to_string(),
+            "line 3: invalid move `move 1 from 2 to 1 now`"
+        );
+    }
+
+    #[test]
+    fn rejects_drawings_without_numbers() {
+        let numbering = |line: usize, text: &str| ParseError {
+            line,
+            kind: ParseErrorKind::InvalidNumbering(text.to_string()),
+        };
+
+        assert_eq!(
+            "[C]\n[M]     [D]\n[Z] [N] [P]\n[M] [C] [D]".parse::<Storage>(),
+            Err(numbering(4, "[M] [C] [D]"))
+        );
+        assert_eq!(
+            "[A]     [B]\n 1   3".parse::<Storage>(),
+            Err(numbering(2, " 1   3"))
+        );
+        assert_eq!("".parse::<Storage>(), Err(numbering(1, "")));
+        assert_eq!(
+            parse_error("[A]\n\nmove 1 from 1 to 1"),
+            numbering(1, "[A]")
+        );
+    }
+
+    #[test]
+    fn solve_start_inverts_the_moves() {
+        let input = parse_input(&example()).expect("valid input");
+
+        for model in [CraneModel::Mover9000, CraneModel::Mover9001] {
+            let end = state_after(&input, &model, usize::MAX).expect("legal moves");
+            // solved from the drawing, just like `--solve-start` does
+            let drawn = end.to_string().parse::<Storage>().expect("valid drawing");
+            assert_eq!(drawn, end);
+
+            let start =
+                solve_start(&drawn, &input.moves, input.first_line, &model).expect("start exists");
+            assert_eq!(start, input.start, "crane {model}");
+
+            let diffs = check_pair(&start, &end, &input.moves, input.first_line, &model)
+                .expect("legal moves");
+            assert!(diffs.is_empty(), "crane {model}");
+        }
+    }
+
+    #[test]
+    fn solve_start_rejects_impossible_ends() {
+        let input = parse_input(&example()).expect("valid input");
+        let end = "[A]\n 1   2   3".parse::<Storage>().expect("valid drawing");
+
+        let error = solve_start(&end, &input.moves, input.first_line, &CraneModel::Mover9000)
+            .expect_err("no start");
+        assert!(error.starts_with("cannot undo line 9 (step 4)"), "{error}");
+    }
+
+    /// Twelve stacks of labels up to four characters wide, some stacks empty.
+    fn wide_yard() -> Storage {
+        let stacks = (0..12)
+            .map(|i| {
+                let labels = (0..i % 5).map(|level| "ABCD"[..1 + (i + level) % 4].to_string());
+                Stack::from(labels.map(Crate::new).collect_vec())
+            })
+            .collect_vec();
+        Storage::new(stacks)
+    }
+
+    #[test]
+    fn drawing_round_trip() {
+        let example = parse_input(&example()).expect("valid input").start;
+
+        for storage in [example, wide_yard()] {
+            let drawing = storage.to_string();
+            let parsed = drawing.parse::<Storage>().expect("valid drawing");
+
+            assert_eq!(parsed, storage, "{drawing}");
+            assert_eq!(parsed.to_string(), drawing);
+        }
+        assert!(wide_yard().to_string().ends_with("  11     12"));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut quoted = wide_yard();
+        quoted.stacks[0] = Stack::from(vec![Crate::new("a\"b\\c/\u{1}".to_string())]);
+
+        for storage in [wide_yard(), quoted, Storage::new(Vec::new())] {
+            let json = storage.to_json();
+            let parsed = Storage::from_json(&json).expect("valid json");
+
+            assert_eq!(parsed, storage, "{json}");
+            assert_eq!(parsed.to_json(), json);
+        }
+
+        let spaced = "{ \"stacks\" : [ [ \"A\" , \"\\u0042\" ] , [ ] ] }\n";
+        assert_eq!(
+            Storage::from_json(spaced).expect("valid json").to_json(),
+            r#"{"stacks": [["A", "B"], []]}"#
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let error = |json: &str| Storage::from_json(json).expect_err("invalid json");
+
+        assert_eq!(
+            error(r#"{"stacks": [["A"], "B"]}"#),
+            "expected `[` at offset 19, found `\"`"
+        );
+        assert_eq!(error(r#"{"crates": []}"#), "unknown key `crates`");
+        assert_eq!(
+            error(r#"{"stacks": [["A"]]"#),
+            "expected `}`, found the end"
+        );
+        assert_eq!(
+            error(r#"{"stacks": [["[A]"]]}"#),
+            "invalid crate label `[A]`"
+        );
+        assert_eq!(
+            error(r#"{"stacks": []} []"#),
+            "expected the end at offset 15, found `[`"
+        );
+    }
+
+    #[test]
+    fn example_top_rows() {
+        let input = parse_input(&example()).expect("valid input");
+        let top_row = |model| {
+            state_after(&input, &model, usize::MAX)
+                .expect("legal moves")
+                .top_row()
+        };
+
+        assert_eq!(top_row(CraneModel::Mover9000), "CMZ");
+        assert_eq!(top_row(CraneModel::Mover9001), "MCD");
+    }
+
+    /// Moves most of a large yard around, which took seconds while a move was linear in the
+    /// number of crates moved. Run with `cargo test --release --bin day5 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_large_moves() {
+        let (num_stacks, num_crates, num_moves) = (9, 900_000, 2_000);
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        let per_stack = num_crates / num_stacks;
+        let yard = || {
+            (0..num_stacks)
+                .map(|i| crates(i * per_stack..(i + 1) * per_stack))
+                .collect_vec()
+        };
+
+        // lifts most of a random stack onto another one
+        let mut lens = vec![per_stack; num_stacks];
+        let moves = (0..num_moves)
+            .map(|_| {
+                let from = random.below(num_stacks);
+                let to = (from + 1 + random.below(num_stacks - 1)) % num_stacks;
+                let cnt = lens[from] - random.below(lens[from] / 10 + 1);
+                lens[from] -= cnt;
+                lens[to] += cnt;
+                Move::new(cnt, from + 1, to + 1)
+            })
+            .collect_vec();
+
+        let mut storage = Storage::new(yard().into_iter().map(Stack::from).collect_vec());
+        let mut reference = yard();
+        let start = std::time::Instant::now();
+        for m in &moves {
+            let at = reference[m.from_idx - 1].len() - m.cnt;
+            let lifted = reference[m.from_idx - 1].split_off(at);
+            reference[m.to_idx - 1].extend(lifted.into_iter().rev());
+        }
+        let linear = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut crane = CrateMover9000;
+        for m in &moves {
+            crane.execute(&mut storage, m);
+        }
+        let treap = start.elapsed();
+
+        println!("{num_moves} moves of {num_crates} crates: vec {linear:?}, treap {treap:?}");
+        for (stack, expected) in storage.stacks.iter().zip(&reference) {
+            assert_stack(stack, expected);
+        }
+        assert!(treap < linear);
+    }
+}
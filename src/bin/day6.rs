@@ -1,65 +1,630 @@
-use std::collections::HashSet;
-use std::fs::File;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
 use std::hash::Hash;
 use std::io::{self, BufRead};
 use std::path::PathBuf;
 
-fn char_windows<'a>(src: &'a str, win_size: usize) -> impl Iterator<Item = &'a str> {
-    src.char_indices().flat_map(move |(from, _)| {
-        src[from..]
-            .char_indices()
-            .skip(win_size - 1)
-            .next()
-            .map(|(to, c)| &src[from..from + to + c.len_utf8()])
-    })
+use itertools::{Either, Itertools};
+
+/// Remembers the offset at which every item was seen last.
+trait SeenTable<T> {
+    /// Stores the offset of the item, returning the previous one.
+    fn insert(&mut self, item: T, offset: usize) -> Option<usize>;
+}
+
+impl<T: Eq + Hash> SeenTable<T> for HashMap<T, usize> {
+    fn insert(&mut self, item: T, offset: usize) -> Option<usize> {
+        HashMap::insert(self, item, offset)
+    }
+}
+
+/// Fast path for bytes: a plain lookup table instead of hashing every item.
+#[derive(Debug)]
+struct ByteTable([usize; 256]);
+
+impl Default for ByteTable {
+    fn default() -> Self {
+        Self([usize::MAX; 256])
+    }
+}
+
+impl SeenTable<u8> for ByteTable {
+    fn insert(&mut self, item: u8, offset: usize) -> Option<usize> {
+        let last = std::mem::replace(&mut self.0[item as usize], offset);
+        (last != usize::MAX).then_some(last)
+    }
 }
 
-fn has_unique_elements<T>(iter: T) -> bool
+/// Characters in a byte table as long as they are ASCII, everything else is hashed.
+#[derive(Debug, Default)]
+struct CharTable {
+    ascii: ByteTable,
+    other: HashMap<char, usize>,
+}
+
+impl SeenTable<char> for CharTable {
+    fn insert(&mut self, item: char, offset: usize) -> Option<usize> {
+        if item.is_ascii() {
+            self.ascii.insert(item as u8, offset)
+        } else {
+            self.other.insert(item, offset)
+        }
+    }
+}
+
+/// Finds windows of `win_size` distinct items, one item at a time.
+///
+/// Keeps the offset at which every item was seen last, so the start of the longest run of
+/// distinct items ending at the current one is known after a single lookup. The whole stream is
+/// therefore scanned once, regardless of the window size.
+#[derive(Debug, Clone)]
+struct Detector<T, S> {
+    win_size: usize,
+    alphabet: Option<HashSet<T>>,
+    last_seen: S,
+    start: usize,
+}
+
+impl<T: Eq + Hash, S: SeenTable<T> + Default> Detector<T, S> {
+    fn new(win_size: usize, alphabet: Option<HashSet<T>>) -> Self {
+        Self {
+            win_size,
+            alphabet,
+            last_seen: S::default(),
+            start: 0,
+        }
+    }
+
+    /// Feeds the item at `offset`, returning whether it completes a marker.
+    ///
+    /// Items outside the alphabet can never be part of a marker.
+    fn push(&mut self, offset: usize, item: T) -> bool {
+        if self
+            .alphabet
+            .as_ref()
+            .is_some_and(|alphabet| !alphabet.contains(&item))
+        {
+            self.start = offset + 1;
+            return false;
+        }
+
+        if let Some(last) = self.last_seen.insert(item, offset) {
+            self.start = self.start.max(last + 1);
+        }
+
+        offset + 1 - self.start >= self.win_size
+    }
+
+    /// Forgets everything before `offset`, so the next marker cannot overlap the previous one.
+    fn restart(&mut self, offset: usize) {
+        self.start = offset;
+    }
+}
+
+/// Iterator over the end offsets of all markers in a stream.
+struct Markers<I, T, S> {
+    items: I,
+    detector: Detector<T, S>,
+    offset: usize,
+}
+
+impl<I, T, S> Markers<I, T, S>
 where
-    T: IntoIterator,
-    T::Item: Eq + Hash,
+    I: Iterator<Item = T>,
+    T: Eq + Hash,
+    S: SeenTable<T> + Default,
 {
-    let mut uniq = HashSet::new();
-    iter.into_iter().all(move |x| uniq.insert(x))
+    fn new(items: I, win_size: usize) -> Self {
+        Self {
+            items,
+            detector: Detector::new(win_size, None),
+            offset: 0,
+        }
+    }
+}
+
+impl<I, T, S> Iterator for Markers<I, T, S>
+where
+    I: Iterator<Item = T>,
+    T: Eq + Hash,
+    S: SeenTable<T> + Default,
+{
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for item in self.items.by_ref() {
+            let found = self.detector.push(self.offset, item);
+            self.offset += 1;
+
+            if found {
+                return Some(self.offset);
+            }
+        }
+
+        None
+    }
+}
+
+/// Markers in a slice of arbitrary items.
+fn markers<T: Eq + Hash + Clone>(items: &[T], win_size: usize) -> impl Iterator<Item = usize> + '_ {
+    Markers::<_, T, HashMap<T, usize>>::new(items.iter().cloned(), win_size)
+}
+
+/// Markers in binary data.
+fn byte_markers(bytes: &[u8], win_size: usize) -> impl Iterator<Item = usize> + '_ {
+    Markers::<_, u8, ByteTable>::new(bytes.iter().copied(), win_size)
+}
+
+/// Markers in text, with offsets counted in characters. ASCII text takes the byte fast path.
+fn str_markers(src: &str, win_size: usize) -> impl Iterator<Item = usize> + '_ {
+    if src.is_ascii() {
+        Either::Left(byte_markers(src.as_bytes(), win_size))
+    } else {
+        Either::Right(Markers::<_, char, HashMap<char, usize>>::new(
+            src.chars(),
+            win_size,
+        ))
+    }
+}
+
+/// Marker lengths and alphabet of the datastream protocol.
+#[derive(Debug, Clone)]
+struct Protocol {
+    packet_marker: usize,
+    message_marker: usize,
+    alphabet: Option<HashSet<char>>,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self {
+            packet_marker: 4,
+            message_marker: 14,
+            alphabet: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// Everything before the first start-of-packet marker.
+    Noise,
+    Packet,
+    Message,
+}
+
+/// A part of the stream following a marker, up to the start of the next marker.
+///
+/// Offsets are counted in characters: `marker` is where the marker starts and `offset` where the
+/// data following it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame<'a> {
+    kind: FrameKind,
+    marker: usize,
+    offset: usize,
+    data: &'a str,
+}
+
+/// Splits a stream into frames.
+///
+/// A start-of-packet marker opens a packet, whose data runs up to the start-of-message marker
+/// opening the message. The message in turn runs up to the next start-of-packet marker.
+struct Decoder<'a> {
+    src: &'a str,
+    protocol: Protocol,
+    chars: std::iter::Enumerate<std::str::CharIndices<'a>>,
+    current: Option<Frame<'a>>,
+    data_start: usize,
+    detector: Detector<char, HashMap<char, usize>>,
+    // byte indices of the last characters, as many as the marker searched for is long
+    window: VecDeque<usize>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(src: &'a str, protocol: Protocol) -> Self {
+        let detector = Detector::new(protocol.packet_marker, protocol.alphabet.clone());
+
+        Self {
+            src,
+            chars: src.char_indices().enumerate(),
+            current: Some(Frame {
+                kind: FrameKind::Noise,
+                marker: 0,
+                offset: 0,
+                data: "",
+            }),
+            data_start: 0,
+            detector,
+            protocol,
+            window: VecDeque::new(),
+        }
+    }
+
+    fn next_kind(kind: FrameKind) -> FrameKind {
+        match kind {
+            FrameKind::Noise | FrameKind::Message => FrameKind::Packet,
+            FrameKind::Packet => FrameKind::Message,
+        }
+    }
+
+    fn marker_len(&self, kind: FrameKind) -> usize {
+        match kind {
+            FrameKind::Noise => 0,
+            FrameKind::Packet => self.protocol.packet_marker,
+            FrameKind::Message => self.protocol.message_marker,
+        }
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut current = self.current.take()?;
+        let kind = Self::next_kind(current.kind);
+        let marker_len = self.marker_len(kind);
+
+        for (offset, (idx, c)) in self.chars.by_ref() {
+            self.window.push_back(idx);
+            if self.window.len() > marker_len {
+                self.window.pop_front();
+            }
+
+            if self.detector.push(offset, c) {
+                let marker_start = self.window.front().copied().unwrap_or(idx);
+                current.data = &self.src[self.data_start..marker_start];
+
+                self.data_start = idx + c.len_utf8();
+                self.current = Some(Frame {
+                    kind,
+                    marker: offset + 1 - marker_len,
+                    offset: offset + 1,
+                    data: "",
+                });
+
+                self.detector = Detector::new(
+                    self.marker_len(Self::next_kind(kind)),
+                    self.protocol.alphabet.clone(),
+                );
+                self.detector.restart(offset + 1);
+                self.window.clear();
+
+                return Some(current);
+            }
+        }
+
+        current.data = &self.src[self.data_start..];
+        Some(current)
+    }
+}
+
+#[derive(Debug)]
+struct Options {
+    protocol: Protocol,
+    frames: bool,
+    binary: Option<PathBuf>,
+    sample_width: usize,
+    stdin: bool,
+    // print the end of every marker, not only the first one
+    positions: bool,
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            protocol: Protocol::default(),
+            frames: false,
+            binary: None,
+            sample_width: 1,
+            stdin: false,
+            positions: false,
+        };
+
+        fn length(value: Option<String>, arg: &str) -> Result<usize, String> {
+            match value
+                .ok_or(format!("{arg} expects a marker length"))?
+                .parse()
+            {
+                Ok(0) => Err(format!("{arg} has to be at least 1")),
+                Ok(len) => Ok(len),
+                Err(e) => Err(format!("invalid length for {arg}: {e}")),
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--frames" => options.frames = true,
+                "--stdin" => options.stdin = true,
+                "--positions" => options.positions = true,
+                "--sample-width" => options.sample_width = length(args.next(), "--sample-width")?,
+                "--binary" => {
+                    options.binary =
+                        Some(PathBuf::from(args.next().ok_or("--binary expects a file")?))
+                }
+                "--packet-marker" => {
+                    options.protocol.packet_marker = length(args.next(), "--packet-marker")?
+                }
+                "--message-marker" => {
+                    options.protocol.message_marker = length(args.next(), "--message-marker")?
+                }
+                "--alphabet" => {
+                    let alphabet = args.next().ok_or("--alphabet expects the characters")?;
+                    options.protocol.alphabet = Some(alphabet.chars().collect());
+                }
+                a => return Err(format!("unknown argument: {a}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Scans a stream incrementally for its first start-of-packet and start-of-message markers.
+///
+/// The stream is fed as UTF-8 bytes but decoded into characters, so offsets are counted in
+/// characters just like for a whole line. Invalid bytes count as a replacement character.
+struct Scanner {
+    packet: Detector<char, CharTable>,
+    message: Detector<char, CharTable>,
+    offset: usize,
+    // start of a character whose remaining bytes are still to come
+    pending: Vec<u8>,
+    result: StreamResult,
+}
+
+impl Scanner {
+    fn new(protocol: &Protocol) -> Self {
+        Self {
+            packet: Detector::new(protocol.packet_marker, protocol.alphabet.clone()),
+            message: Detector::new(protocol.message_marker, protocol.alphabet.clone()),
+            offset: 0,
+            pending: Vec::new(),
+            result: StreamResult::default(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset == 0 && self.pending.is_empty()
+    }
+
+    fn push(&mut self, c: char) {
+        if self.packet.push(self.offset, c) && self.result.packet.is_none() {
+            self.result.packet = Some(self.offset + 1);
+        }
+        if self.message.push(self.offset, c) && self.result.message.is_none() {
+            self.result.message = Some(self.offset + 1);
+        }
+        self.offset += 1;
+    }
+
+    /// Feeds the next bytes of the stream, a character may be split across several calls.
+    fn feed(&mut self, bytes: &[u8]) {
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut rest = if pending.is_empty() {
+            bytes
+        } else {
+            pending.extend_from_slice(bytes);
+            &pending
+        };
+
+        while !rest.is_empty() {
+            let (valid, error) = match std::str::from_utf8(rest) {
+                Ok(valid) => (valid, None),
+                Err(e) => {
+                    let valid =
+                        std::str::from_utf8(&rest[..e.valid_up_to()]).expect("valid prefix");
+                    (valid, Some(e))
+                }
+            };
+            valid.chars().for_each(|c| self.push(c));
+            rest = &rest[valid.len()..];
+
+            match error.map(|e| e.error_len()) {
+                None => break,
+                // the character is completed by the next call
+                Some(None) => {
+                    self.pending = rest.to_vec();
+                    break;
+                }
+                Some(Some(len)) => {
+                    self.push(char::REPLACEMENT_CHARACTER);
+                    rest = &rest[len..];
+                }
+            }
+        }
+    }
+
+    /// Ends the stream, a character that was never completed counts as invalid.
+    fn finish(mut self) -> StreamResult {
+        if !self.pending.is_empty() {
+            self.push(char::REPLACEMENT_CHARACTER);
+        }
+        self.result
+    }
+}
+
+/// First markers of a single stream, `None` if the stream has no such marker.
+#[derive(Debug, Default, Clone, Copy)]
+struct StreamResult {
+    packet: Option<usize>,
+    message: Option<usize>,
+}
+
+impl Display for StreamResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.packet {
+            Some(offset) => write!(f, "start-of-packet after {offset}, ")?,
+            None => write!(f, "no start-of-packet marker, ")?,
+        }
+        match self.message {
+            Some(offset) => write!(f, "start-of-message after {offset}"),
+            None => write!(f, "no start-of-message marker"),
+        }
+    }
+}
+
+/// Treats every line of `input` as a stream of its own, reporting each as soon as it ends.
+///
+/// Lines are never held in memory as a whole, so arbitrarily long signals can be processed.
+fn scan(
+    mut input: impl BufRead,
+    protocol: &Protocol,
+    mut report: impl FnMut(StreamResult),
+) -> io::Result<()> {
+    let mut scanner = Scanner::new(protocol);
+    // a `\r` at the end of the buffer, only known to end the line once the next buffer is read
+    let mut carriage_return = false;
+
+    loop {
+        let buf = input.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+
+        let len = buf.len();
+        for (i, chunk) in buf.split(|b| *b == b'\n').enumerate() {
+            if i > 0 {
+                let done = std::mem::replace(&mut scanner, Scanner::new(protocol));
+                report(done.finish());
+            } else if carriage_return && !chunk.is_empty() {
+                // not followed by `\n`, so part of the line after all
+                scanner.feed(b"\r");
+            }
+
+            carriage_return = chunk.ends_with(b"\r");
+            scanner.feed(chunk.strip_suffix(b"\r").unwrap_or(chunk));
+        }
+        input.consume(len);
+    }
+
+    if carriage_return {
+        scanner.feed(b"\r");
+    }
+    if !scanner.is_empty() {
+        report(scanner.finish());
+    }
+
+    Ok(())
+}
+
+fn scan_stdin(protocol: &Protocol) -> io::Result<()> {
+    let mut stream = 0;
+
+    scan(io::stdin().lock(), protocol, |result| {
+        stream += 1;
+        println!("stream {stream}: {result}");
+    })
+}
+
+/// Prints the first marker of every stream, prefixed with the stream number if there are several.
+///
+/// With `positions` the ends of all the other markers are listed as well.
+fn task(lines: &[String], task: usize, name: &str, win_size: usize, positions: bool) {
+    for (i, line) in lines.iter().enumerate() {
+        let prefix = if lines.len() > 1 {
+            format!("stream {}: ", i + 1)
+        } else {
+            String::new()
+        };
+        let markers = str_markers(line, win_size).collect_vec();
+
+        match markers.first() {
+            Some(first) => {
+                println!("{task}. {prefix}first {name} after {first}");
+                println!("   {} {name} markers in total", markers.len());
+                if positions {
+                    println!("   after {}", markers.iter().join(", "));
+                }
+            }
+            None => println!("{task}. {prefix}no {name} marker"),
+        }
+    }
 }
 
 fn main() {
     let file_name = PathBuf::from("./input/day6");
 
-    // task 1
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => panic!("{e}"),
+    };
+
+    if options.stdin {
+        if let Err(e) = scan_stdin(&options.protocol) {
+            println!("Error: {}", e);
+        }
+        return;
+    }
+
     match read_lines(&file_name) {
-        Ok(mut lines) => {
-            let line = lines.next().expect("invalid input").expect("invalid input");
-            let windows = char_windows(&line, 4);
+        Ok(lines) => {
+            let lines = lines.map_while(Result::ok).collect_vec();
 
-            let (cnt, _) = windows
-                .map(|w| has_unique_elements(w.chars()))
-                .enumerate()
-                .skip_while(|(_, b)| !b)
-                .next()
-                .expect("nothing unique...");
+            let protocol = &options.protocol;
+            let positions = options.positions;
+            task(
+                &lines,
+                1,
+                "start-of-packet",
+                protocol.packet_marker,
+                positions,
+            );
+            task(
+                &lines,
+                2,
+                "start-of-message",
+                protocol.message_marker,
+                positions,
+            );
 
-            println!("1. first start-of-packet after {}", cnt + 4);
+            // decoded frames
+            if options.frames {
+                for (i, line) in lines.iter().enumerate() {
+                    for frame in Decoder::new(line, options.protocol.clone()) {
+                        println!(
+                            "stream {}: {:?} (marker at {}, data at {}): {}",
+                            i + 1,
+                            frame.kind,
+                            frame.marker,
+                            frame.offset,
+                            frame.data
+                        );
+                    }
+                }
+            }
         }
         Err(e) => println!("Error: {}", e),
     }
 
-    // task 2
-    match read_lines(&file_name) {
-        Ok(mut lines) => {
-            let line = lines.next().expect("invalid input").expect("invalid input");
-            let windows = char_windows(&line, 14);
+    // markers in a binary capture
+    if let Some(binary) = &options.binary {
+        match fs::read(binary) {
+            Ok(bytes) => {
+                let kinds = [
+                    ("start-of-packet", options.protocol.packet_marker),
+                    ("start-of-message", options.protocol.message_marker),
+                ];
 
-            let (cnt, _) = windows
-                .map(|w| has_unique_elements(w.chars()))
-                .enumerate()
-                .skip_while(|(_, b)| !b)
-                .next()
-                .expect("nothing unique...");
+                for (name, win_size) in kinds {
+                    // samples wider than a byte are compared as a whole
+                    let first = if options.sample_width == 1 {
+                        byte_markers(&bytes, win_size).next()
+                    } else {
+                        let samples = bytes.chunks(options.sample_width).collect_vec();
+                        let first = markers(&samples, win_size).next();
+                        first
+                    };
 
-            println!("1. first start-of-message after {}", cnt + 14);
+                    match first {
+                        Some(offset) => println!("first {name} at sample {offset}"),
+                        None => println!("no {name} marker"),
+                    }
+                }
+            }
+            Err(e) => println!("Error: {}", e),
         }
-        Err(e) => println!("Error: {}", e),
     }
 }
 
@@ -68,3 +633,124 @@ fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
 
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Results of `scan`, reading `input` in chunks of `capacity` bytes.
+    fn scan_results(input: &[u8], capacity: usize) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut results = Vec::new();
+        let reader = io::BufReader::with_capacity(capacity, input);
+
+        scan(reader, &Protocol::default(), |result| {
+            results.push((result.packet, result.message))
+        })
+        .expect("reading from memory");
+        results
+    }
+
+    #[test]
+    fn finds_all_markers() {
+        let src = "mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+
+        assert_eq!(
+            str_markers(src, 14).collect_vec(),
+            vec![19, 25, 26, 27, 28, 29, 30]
+        );
+        assert_eq!(str_markers(src, 4).collect_vec(), (7..=30).collect_vec());
+        assert_eq!(str_markers("ééabé", 3).collect_vec(), vec![4, 5]);
+    }
+
+    fn frames(src: &str, protocol: Protocol) -> Vec<(FrameKind, usize, usize, &str)> {
+        Decoder::new(src, protocol)
+            .map(|frame| (frame.kind, frame.marker, frame.offset, frame.data))
+            .collect_vec()
+    }
+
+    #[test]
+    fn decodes_ascii_frames() {
+        assert_eq!(
+            frames("mjqjpqmgbljsphdztnvjfqwrcgsmlb", Protocol::default()),
+            vec![
+                (FrameKind::Noise, 0, 0, "mjq"),
+                (FrameKind::Packet, 3, 7, "gblj"),
+                (FrameKind::Message, 11, 25, ""),
+                (FrameKind::Packet, 25, 29, "b"),
+            ]
+        );
+        assert_eq!(
+            frames("aaaa", Protocol::default()),
+            vec![(FrameKind::Noise, 0, 0, "aaaa")]
+        );
+    }
+
+    #[test]
+    fn decodes_non_ascii_frames() {
+        let protocol = Protocol {
+            packet_marker: 4,
+            message_marker: 3,
+            alphabet: None,
+        };
+
+        assert_eq!(
+            frames("ééabcéédé€xyzz", protocol),
+            vec![
+                (FrameKind::Noise, 0, 0, "é"),
+                (FrameKind::Packet, 1, 5, "éé"),
+                (FrameKind::Message, 7, 10, "xyzz"),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_frames_of_an_alphabet() {
+        let protocol = Protocol {
+            packet_marker: 2,
+            message_marker: 3,
+            alphabet: Some("abcd".chars().collect()),
+        };
+
+        assert_eq!(
+            frames("aXbcYabcd", protocol),
+            vec![
+                (FrameKind::Noise, 0, 0, "aX"),
+                (FrameKind::Packet, 2, 4, "Y"),
+                (FrameKind::Message, 5, 8, "d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_matches_lines() {
+        let input = "mjqjpqmgbljsphdztnvjfqwrcgsmlb\nééab\nabcé\nbvwbjplbgvbhsrlpgdmjqwftvncz";
+
+        for capacity in 1..8 {
+            let expected = input
+                .lines()
+                .map(|line| (str_markers(line, 4).next(), str_markers(line, 14).next()))
+                .collect_vec();
+            assert_eq!(scan_results(input.as_bytes(), capacity), expected);
+        }
+        assert_eq!(scan_results("ééab".as_bytes(), 1), vec![(None, None)]);
+    }
+
+    #[test]
+    fn scan_strips_line_endings_across_buffers() {
+        let input = b"abc\r\nabcd\r\na\rbc\r\n";
+
+        for capacity in 1..8 {
+            assert_eq!(
+                scan_results(input, capacity),
+                vec![(None, None), (Some(4), None), (Some(4), None)],
+                "capacity {capacity}"
+            );
+        }
+    }
+
+    #[test]
+    fn scan_replaces_invalid_bytes() {
+        assert_eq!(scan_results(b"a\xffbc\xe2", 2), vec![(Some(4), None)]);
+        assert_eq!(scan_results(b"\xff\xffab", 2), vec![(None, None)]);
+    }
+}
@@ -1,25 +1,50 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use itertools::Itertools;
 
+/// Index of a node in the arena of a `FileSystem`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct NodeId(u32);
+
+impl NodeId {
+    const ROOT: NodeId = NodeId(0);
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Directory {
     name: String,
-    parent: u32,
-    entries: Vec<u32>,
+    parent: NodeId,
+    // children in the order they were listed, and by name for lookups
+    entries: Vec<NodeId>,
+    children: HashMap<String, NodeId>,
+    // whether the contents were seen in an `ls` output, otherwise they are incomplete
+    listed: bool,
 }
 
 impl Directory {
-    fn new(name: String, parent: u32, entries: Vec<u32>) -> Self {
+    fn new(name: String, parent: NodeId) -> Self {
         Self {
             name,
             parent,
-            entries,
+            entries: Vec::new(),
+            children: HashMap::new(),
+            listed: false,
         }
     }
 }
@@ -27,12 +52,13 @@ impl Directory {
 #[derive(Debug)]
 struct Fil {
     name: String,
-    size: u32,
+    size: u64,
+    parent: NodeId,
 }
 
 impl Fil {
-    fn new(name: String, size: u32) -> Self {
-        Self { name, size }
+    fn new(name: String, size: u64, parent: NodeId) -> Self {
+        Self { name, size, parent }
     }
 }
 
@@ -43,33 +69,17 @@ enum FsEntry {
 }
 
 impl FsEntry {
-    fn calc_size(&self, fs: &FileSystem) -> u32 {
+    fn name(&self) -> &str {
         match self {
-            Self::Dir(dir) => dir
-                .entries
-                .iter()
-                .map(|entry| fs.nodes[*entry as usize].calc_size(fs))
-                .sum::<u32>(),
-            Self::File(file) => file.size,
+            FsEntry::Dir(dir) => &dir.name,
+            FsEntry::File(file) => &file.name,
         }
     }
 
-    fn fmt(
-        &self,
-        fs: &FileSystem,
-        f: &mut std::fmt::Formatter<'_>,
-        level: u32,
-    ) -> std::fmt::Result {
-        let indent = " |".repeat(level as usize);
+    fn parent(&self) -> NodeId {
         match self {
-            Self::Dir(dir) => {
-                write!(f, "{}\\_{} - {}\n", indent, dir.name, self.calc_size(fs))?;
-                for entry in &dir.entries {
-                    fs.nodes[*entry as usize].fmt(fs, f, level + 1)?;
-                }
-                Ok(())
-            }
-            Self::File(file) => write!(f, "{}{} - {}\n", indent, file.name, file.size),
+            FsEntry::Dir(dir) => dir.parent,
+            FsEntry::File(file) => file.parent,
         }
     }
 }
@@ -77,212 +87,2093 @@ impl FsEntry {
 #[derive(Debug)]
 struct FileSystem {
     nodes: Vec<FsEntry>,
+    // total size of every node, computed once when the filesystem is built
+    sizes: Vec<u64>,
+    // entries the transcript contradicts itself about, the first listing wins
+    conflicts: Vec<Conflict>,
 }
 
 impl FileSystem {
     fn new(nodes: Vec<FsEntry>) -> Self {
-        Self { nodes }
+        let sizes = Self::calc_sizes(&nodes);
+        Self {
+            nodes,
+            sizes,
+            conflicts: Vec::new(),
+        }
     }
-}
 
-impl Display for FileSystem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.nodes[0].fmt(&self, f, 0)
+    /// Computes the sizes of all nodes in a single post-order pass starting at the root.
+    fn calc_sizes(nodes: &[FsEntry]) -> Vec<u64> {
+        let mut sizes = vec![0; nodes.len()];
+        let mut stack = vec![(NodeId::ROOT, false)];
+
+        while let Some((node, children_done)) = stack.pop() {
+            match &nodes[node.index()] {
+                FsEntry::File(file) => sizes[node.index()] = file.size,
+                FsEntry::Dir(dir) if children_done => {
+                    sizes[node.index()] = dir.entries.iter().map(|e| sizes[e.index()]).sum()
+                }
+                FsEntry::Dir(dir) => {
+                    stack.push((node, true));
+                    stack.extend(dir.entries.iter().map(|entry| (*entry, false)));
+                }
+            }
+        }
+
+        sizes
     }
-}
 
-impl From<Vec<Command>> for FileSystem {
-    fn from(commands: Vec<Command>) -> Self {
-        Self::from_iter(commands.into_iter())
+    fn size(&self, node: NodeId) -> u64 {
+        self.sizes[node.index()]
     }
-}
 
-impl FromIterator<Command> for FileSystem {
-    fn from_iter<T: IntoIterator<Item = Command>>(iter: T) -> Self {
-        let mut nodes: Vec<FsEntry> = Vec::new();
-        let mut index = 0_u32;
+    fn name(&self, node: NodeId) -> &str {
+        self.nodes[node.index()].name()
+    }
+
+    /// Looks up the entry called `name` in the directory `dir`.
+    fn child(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        match &self.nodes[dir.index()] {
+            FsEntry::Dir(dir) => dir.children.get(name).copied(),
+            FsEntry::File(_) => None,
+        }
+    }
 
-        nodes.push(FsEntry::Dir(Directory::new("/".to_string(), 0, Vec::new())));
-        index += 1;
+    /// Resolves a path relative to the directory `cwd`, or to the root if it starts with `/`.
+    fn resolve(&self, cwd: NodeId, path: &str) -> Option<NodeId> {
+        let start = if path.starts_with('/') {
+            NodeId::ROOT
+        } else {
+            cwd
+        };
 
-        let mut cur_dir = 0_u32;
+        path.split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .try_fold(start, |node, segment| match &self.nodes[node.index()] {
+                FsEntry::Dir(dir) if segment == ".." => Some(dir.parent),
+                FsEntry::Dir(_) => self.child(node, segment),
+                FsEntry::File(_) => None,
+            })
+    }
 
-        for cmd in iter {
-            match cmd {
-                Command::CD(name) => {
-                    cur_dir = if let FsEntry::Dir(cur_entry) = &nodes[cur_dir as usize] {
-                        if name == "/" {
-                            0_u32
-                        } else if name == ".." {
-                            cur_entry.parent
-                        } else {
-                            let mut new_dir = -1_i32;
-
-                            for entry in &cur_entry.entries {
-                                if let FsEntry::Dir(cur_entry) = &nodes[*entry as usize] {
-                                    if cur_entry.name == name {
-                                        new_dir = *entry as i32;
-                                        break;
-                                    }
-                                }
-                            }
-                            if new_dir == -1 {
-                                panic!("did not find directory {} to switch to...", name);
-                            }
-                            new_dir as u32
-                        }
-                    } else {
-                        panic!("cur dir is no dir?")
-                    }
+    /// Looks up a node by its absolute path.
+    fn lookup(&self, path: &str) -> Option<NodeId> {
+        self.resolve(NodeId::ROOT, path)
+    }
+
+    /// Absolute path of any node.
+    fn path(&self, node: NodeId) -> String {
+        node_path(&self.nodes, node)
+    }
+
+    fn dirs(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| matches!(entry, FsEntry::Dir(_)))
+            .map(|(i, _)| NodeId(i as u32))
+    }
+
+    /// Directories that were only entered with `cd` but never listed.
+    fn unlisted(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| matches!(entry, FsEntry::Dir(dir) if !dir.listed))
+            .map(|(i, _)| NodeId(i as u32))
+    }
+
+    /// Every node below the root with its path and size, sorted by path.
+    fn listing(&self) -> Vec<(String, u64)> {
+        let mut out = Vec::new();
+        let mut stack = vec![(NodeId::ROOT, String::new())];
+
+        while let Some((node, path)) = stack.pop() {
+            if let FsEntry::Dir(dir) = &self.nodes[node.index()] {
+                for entry in &dir.entries {
+                    let child = format!("{}/{}", path, self.name(*entry));
+                    out.push((child.clone(), self.size(*entry)));
+                    stack.push((*entry, child));
                 }
-                Command::LS(content) => {
-                    for entry in content {
-                        if entry.starts_with("dir ") {
-                            let mut entry = entry.split_whitespace();
-                            nodes.push(FsEntry::Dir(Directory::new(
-                                entry.nth(1).expect("no name?").to_string(),
-                                cur_dir,
-                                Vec::new(),
-                            )));
-                        } else {
-                            let entries = entry.split_whitespace().collect_vec();
-                            let size = entries[0]
-                                .parse::<u32>()
-                                .expect("invalid input, could not parse to int");
-
-                            nodes.push(FsEntry::File(Fil::new(entries[1].to_string(), size)));
-                        }
-
-                        let cur = if let FsEntry::Dir(cur) = &mut nodes[cur_dir as usize] {
-                            cur
-                        } else {
-                            panic!("")
-                        };
-                        cur.entries.push(index);
-                        index += 1;
+            }
+        }
+
+        out.sort();
+        out
+    }
+
+    /// Writes the tree below `root` on disk, files are created sparse with their recorded size.
+    ///
+    /// `root` has to be missing or an empty directory, existing files are never overwritten.
+    fn export(&self, root: &Path) -> io::Result<()> {
+        match std::fs::read_dir(root).map(|mut entries| entries.next().is_none()) {
+            Ok(false) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is not empty", root.display()),
+                ))
+            }
+            Ok(true) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => std::fs::create_dir_all(root)?,
+            Err(e) => return Err(e),
+        }
+
+        self.export_node(NodeId::ROOT, root)
+    }
+
+    fn export_node(&self, node: NodeId, path: &Path) -> io::Result<()> {
+        match &self.nodes[node.index()] {
+            FsEntry::Dir(dir) => {
+                if node != NodeId::ROOT {
+                    std::fs::create_dir(path)?;
+                }
+                for entry in &dir.entries {
+                    let name = self.name(*entry);
+                    if name.contains('/') || name == "." || name == ".." {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("{name} can not be used as a file name"),
+                        ));
                     }
+                    self.export_node(*entry, &path.join(name))?;
                 }
+                Ok(())
+            }
+            FsEntry::File(file) => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)?
+                .set_len(file.size),
+        }
+    }
+}
+
+/// Generates a `$ cd`/`$ ls` transcript by walking a real directory, entries sorted by name.
+fn transcript_from_dir(root: &Path) -> io::Result<Vec<String>> {
+    fn walk(dir: &Path, lines: &mut Vec<String>) -> io::Result<()> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut dirs = Vec::new();
+        lines.push("$ ls".to_string());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let meta = entry.metadata()?;
+
+            if meta.is_dir() {
+                lines.push(format!("dir {name}"));
+                dirs.push((name, entry.path()));
+            } else if meta.is_file() {
+                let size = meta.len();
+                lines.push(format!("{size} {name}"));
             }
         }
 
-        FileSystem::new(nodes)
+        for (name, path) in dirs {
+            lines.push(format!("$ cd {name}"));
+            walk(&path, lines)?;
+            lines.push("$ cd ..".to_string());
+        }
+
+        Ok(())
     }
+
+    let mut lines = vec!["$ cd /".to_string()];
+    walk(root, &mut lines)?;
+    Ok(lines)
 }
 
-#[derive(Debug)]
-enum Command {
-    CD(String),
-    LS(Vec<String>),
+/// Exports the filesystem to a temporary directory, walks it again and compares both trees.
+fn round_trip(fs: &FileSystem) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("day7-{}", std::process::id()));
+    if dir.exists() {
+        return Err(format!("{} already exists", dir.display()));
+    }
+
+    let result = fs
+        .export(&dir)
+        .and_then(|_| transcript_from_dir(&dir))
+        .map_err(|e| format!("{}: {}", dir.display(), e))
+        .and_then(|lines| parse_fs(&lines).map_err(|e| e.to_string()))
+        .and_then(|copy| {
+            let (expected, found) = (fs.listing(), copy.listing());
+            match expected.iter().zip(&found).find(|(a, b)| a != b) {
+                None if expected.len() == found.len() => Ok(()),
+                None => Err(format!(
+                    "expected {} entries, found {}",
+                    expected.len(),
+                    found.len()
+                )),
+                Some((a, b)) => Err(format!(
+                    "expected {} - {}, found {} - {}",
+                    a.0, a.1, b.0, b.1
+                )),
+            }
+        });
+
+    std::fs::remove_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    result
 }
 
-impl From<String> for Command {
-    fn from(command: String) -> Self {
-        let command_vec = command
-            .split('\n')
-            .flat_map(|line| if line.is_empty() { None } else { Some(line) })
-            .collect_vec();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// The original `|\_name - size` tree.
+    Classic,
+    /// Box drawing like `tree`, with human readable sizes.
+    Tree,
+    Json,
+    /// Directory sizes and paths like `du`, children first.
+    Du,
+    /// Graphviz digraph.
+    Dot,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "classic" => Ok(Format::Classic),
+            "tree" => Ok(Format::Tree),
+            "json" => Ok(Format::Json),
+            "du" => Ok(Format::Du),
+            "dot" => Ok(Format::Dot),
+            _ => Err(format!("unknown format: {format}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+    /// The order of the `ls` output.
+    Listing,
+    Name,
+    /// Largest first.
+    Size,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(sort: &str) -> Result<Self, Self::Err> {
+        match sort {
+            "listing" => Ok(SortBy::Listing),
+            "name" => Ok(SortBy::Name),
+            "size" => Ok(SortBy::Size),
+            _ => Err(format!("unknown sort order: {sort}")),
+        }
+    }
+}
+
+/// Size with a binary unit suffix, similar to `du -h`.
+fn human_size(size: u64) -> String {
+    let mut value = size as f64;
+    let mut units = ["", "K", "M", "G", "T"].into_iter().peekable();
+    let mut unit = units.next().unwrap_or_default();
 
-        let mut command = command_vec.into_iter();
+    while value >= 1024.0 && units.peek().is_some() {
+        value /= 1024.0;
+        unit = units.next().unwrap_or_default();
+    }
+
+    match unit {
+        "" => size.to_string(),
+        unit if value < 10.0 => format!("{value:.1}{unit}"),
+        unit => format!("{value:.0}{unit}"),
+    }
+}
 
-        let mut call = command.next().unwrap().split_whitespace();
+fn json_string(val: &str) -> String {
+    let mut out = String::from('"');
 
-        match call.nth(0).expect("invalid input") {
-            "cd" => Command::CD(call.next().expect("").to_string()),
-            "ls" => Command::LS(command.map(|line| line.to_string()).collect_vec()),
-            a => panic!("invalid input: {}", a),
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
         }
     }
+
+    out.push('"');
+    out
+}
+
+/// The subtree below a single node in one of the output formats.
+struct Render<'a> {
+    fs: &'a FileSystem,
+    node: NodeId,
+    format: Format,
+    // levels below `node` that are shown, all if unset
+    depth: Option<usize>,
+    sort: SortBy,
 }
 
-fn task1(lines: Vec<String>) {
-    let commands = lines
-        .join("\n")
-        .split(|char| char == '$')
-        .flat_map(|line| {
-            if line.is_empty() {
-                None
+impl<'a> Render<'a> {
+    fn new(fs: &'a FileSystem, node: NodeId) -> Self {
+        Self {
+            fs,
+            node,
+            format: Format::Classic,
+            depth: None,
+            sort: SortBy::Listing,
+        }
+    }
+
+    /// Children of `node` in display order, none once the depth limit is reached.
+    fn children(&self, node: NodeId, level: usize) -> Vec<NodeId> {
+        let entries = match &self.fs.nodes[node.index()] {
+            FsEntry::Dir(dir) if self.depth.is_none_or(|depth| level < depth) => &dir.entries,
+            _ => return Vec::new(),
+        };
+
+        match self.sort {
+            SortBy::Listing => entries.clone(),
+            SortBy::Name => entries
+                .iter()
+                .copied()
+                .sorted_by_key(|entry| self.fs.name(*entry))
+                .collect_vec(),
+            SortBy::Size => entries
+                .iter()
+                .copied()
+                .sorted_by_key(|entry| std::cmp::Reverse(self.fs.size(*entry)))
+                .collect_vec(),
+        }
+    }
+
+    fn is_dir(&self, node: NodeId) -> bool {
+        matches!(self.fs.nodes[node.index()], FsEntry::Dir(_))
+    }
+
+    fn classic(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        node: NodeId,
+        level: usize,
+    ) -> std::fmt::Result {
+        let indent = " |".repeat(level);
+        let name = self.fs.name(node);
+
+        if self.is_dir(node) {
+            writeln!(f, "{}\\_{} - {}", indent, name, self.fs.size(node))?;
+        } else {
+            writeln!(f, "{}{} - {}", indent, name, self.fs.size(node))?;
+        }
+
+        for entry in self.children(node, level) {
+            self.classic(f, entry, level + 1)?;
+        }
+        Ok(())
+    }
+
+    fn tree(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        node: NodeId,
+        level: usize,
+        prefix: &str,
+    ) -> std::fmt::Result {
+        let children = self.children(node, level);
+
+        for (i, entry) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
             } else {
-                Some(line.chars().skip(1).collect::<String>()) // skip the space after $
+                ("├── ", "│   ")
+            };
+
+            writeln!(
+                f,
+                "{}{}{} ({})",
+                prefix,
+                branch,
+                self.fs.name(*entry),
+                human_size(self.fs.size(*entry))
+            )?;
+            self.tree(f, *entry, level + 1, &format!("{prefix}{indent}"))?;
+        }
+        Ok(())
+    }
+
+    fn json(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        node: NodeId,
+        level: usize,
+    ) -> std::fmt::Result {
+        let kind = if self.is_dir(node) { "dir" } else { "file" };
+        write!(
+            f,
+            "{{\"name\":{},\"type\":\"{}\",\"size\":{}",
+            json_string(self.fs.name(node)),
+            kind,
+            self.fs.size(node)
+        )?;
+
+        if self.is_dir(node) && self.depth.is_none_or(|depth| level < depth) {
+            write!(f, ",\"children\":[")?;
+            for (i, entry) in self.children(node, level).into_iter().enumerate() {
+                if i > 0 {
+                    write!(f, ",")?;
+                }
+                self.json(f, entry, level + 1)?;
             }
-        })
-        .map(|group| Command::from(group))
-        .collect_vec();
+            write!(f, "]")?;
+        }
+        write!(f, "}}")
+    }
 
-    let fs = FileSystem::from(commands);
+    fn du(&self, f: &mut std::fmt::Formatter<'_>, node: NodeId, level: usize) -> std::fmt::Result {
+        for entry in self.children(node, level) {
+            if self.is_dir(entry) {
+                self.du(f, entry, level + 1)?;
+            }
+        }
+        writeln!(f, "{}\t{}", self.fs.size(node), self.fs.path(node))
+    }
 
-    println!("{}", fs);
+    fn dot(&self, f: &mut std::fmt::Formatter<'_>, node: NodeId, level: usize) -> std::fmt::Result {
+        let shape = if self.is_dir(node) { "folder" } else { "note" };
+        let name = self
+            .fs
+            .name(node)
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        writeln!(
+            f,
+            "    n{} [shape={}, label=\"{}\\n{}\"];",
+            node,
+            shape,
+            name,
+            self.fs.size(node)
+        )?;
 
-    let acc_size = fs
-        .nodes
-        .iter()
-        .flat_map(|entry| match entry {
-            FsEntry::Dir(_) => Some(entry.calc_size(&fs)),
-            FsEntry::File(_) => None,
-        })
-        .filter(|entry| *entry <= 100000)
-        .sum::<u32>();
+        for entry in self.children(node, level) {
+            writeln!(f, "    n{} -> n{};", node, entry)?;
+            self.dot(f, entry, level + 1)?;
+        }
+        Ok(())
+    }
+}
 
-    println!(
-        "1. accumulated size of all directories with less than 100000 size: {}",
-        acc_size
-    );
+impl Display for Render<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.format {
+            Format::Classic => self.classic(f, self.node, 0),
+            Format::Tree => {
+                writeln!(
+                    f,
+                    "{} ({})",
+                    self.fs.path(self.node),
+                    human_size(self.fs.size(self.node))
+                )?;
+                self.tree(f, self.node, 0, "")
+            }
+            Format::Json => {
+                self.json(f, self.node, 0)?;
+                writeln!(f)
+            }
+            Format::Du => self.du(f, self.node, 0),
+            Format::Dot => {
+                writeln!(f, "digraph fs {{")?;
+                self.dot(f, self.node, 0)?;
+                writeln!(f, "}}")
+            }
+        }
+    }
 }
 
-fn task2(lines: Vec<String>) {
-    let commands = lines
-        .join("\n")
-        .split(|char| char == '$')
-        .flat_map(|line| {
-            if line.is_empty() {
-                None
-            } else {
-                Some(line.chars().skip(1).collect::<String>()) // skip the space after $
+impl Display for FileSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Render::new(self, NodeId::ROOT).fmt(f)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ConflictKind {
+    KindMismatch,
+    SizeMismatch {
+        old: u64,
+        new: u64,
+    },
+    /// `cd` into something that was listed as a file.
+    NotADirectory,
+    /// `ls` output that can not be placed since its directory is unknown.
+    Dropped,
+}
+
+/// An entry the transcript shows differently than before, at the given line.
+#[derive(Debug, PartialEq, Eq)]
+struct Conflict {
+    line: usize,
+    path: String,
+    kind: ConflictKind,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            ConflictKind::KindMismatch => {
+                write!(f, "{} is listed both as file and as directory", self.path)
             }
-        })
-        .map(|group| Command::from(group))
-        .collect_vec();
+            ConflictKind::SizeMismatch { old, new } => write!(
+                f,
+                "{} is listed with size {} and with size {}",
+                self.path, old, new
+            ),
+            ConflictKind::NotADirectory => {
+                write!(f, "can not change into {}, it is a file", self.path)
+            }
+            ConflictKind::Dropped => {
+                write!(f, "{} is dropped, its directory is unknown", self.path)
+            }
+        }
+    }
+}
 
-    let fs = FileSystem::from(commands);
+/// Absolute path of `node`, following the parent links up to the root.
+fn node_path(nodes: &[FsEntry], mut node: NodeId) -> String {
+    let mut segments = Vec::new();
 
-    let fs_size: u32 = fs
-        .nodes
-        .iter()
-        .flat_map(|entry| match entry {
-            FsEntry::Dir(_) => None,
-            FsEntry::File(_) => Some(entry.calc_size(&fs)),
-        })
-        .sum();
-    println!("size of the whole fs: {}", fs_size);
-
-    let needed_size = fs
-        .nodes
-        .iter()
-        .flat_map(|entry| match entry {
-            FsEntry::Dir(_) => Some(entry.calc_size(&fs)),
-            FsEntry::File(_) => None,
-        })
-        .sorted()
-        .find(move |size| *size >= fs_size - (70_000_000 - 30_000_000))
-        .expect("not found");
+    while node != NodeId::ROOT {
+        segments.push(nodes[node.index()].name());
+        node = nodes[node.index()].parent();
+    }
 
-    println!("2. size of the directory to remove: {}", needed_size);
+    format!("/{}", segments.into_iter().rev().join("/"))
 }
 
-fn main() {
-    let file_name = PathBuf::from("./input/day7");
+/// Looks up the entry `name` of `dir` while the filesystem is being built.
+fn find_entry(nodes: &[FsEntry], dir: NodeId, name: &str) -> Option<NodeId> {
+    match &nodes[dir.index()] {
+        FsEntry::Dir(dir) => dir.children.get(name).copied(),
+        FsEntry::File(_) => None,
+    }
+}
 
-    match read_lines(&file_name) {
-        Ok(lines) => {
-            let lines = lines.flatten().collect_vec();
-            task1(lines.clone());
-            task2(lines);
+/// Replays the commands of a transcript.
+///
+/// Listing a directory again merges the entries into what is already known, and changing into a
+/// directory that was never listed creates it on the fly. Contradicting entries are collected as
+/// conflicts while the first version of the entry is kept. After changing into a file, the output
+/// of `ls` is dropped with a warning until the next absolute `cd`.
+struct Builder {
+    nodes: Vec<FsEntry>,
+    // the path that could not be resolved after changing into a file, until the next absolute `cd`
+    cwd: Result<NodeId, String>,
+    conflicts: Vec<Conflict>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            nodes: vec![FsEntry::Dir(Directory::new("/".to_string(), NodeId::ROOT))],
+            cwd: Ok(NodeId::ROOT),
+            conflicts: Vec::new(),
         }
-        Err(e) => println!("Error during reading: {:?}", e),
+    }
+
+    fn dir_mut(&mut self, dir: NodeId) -> &mut Directory {
+        match &mut self.nodes[dir.index()] {
+            FsEntry::Dir(dir) => dir,
+            FsEntry::File(file) => panic!("{} is no dir", file.name),
+        }
+    }
+
+    fn entry_path(&self, dir: NodeId, name: &str) -> String {
+        format!(
+            "{}/{}",
+            node_path(&self.nodes, dir).trim_end_matches('/'),
+            name
+        )
+    }
+
+    fn conflict(&mut self, line: usize, dir: NodeId, name: &str, kind: ConflictKind) {
+        let path = self.entry_path(dir, name);
+        self.conflicts.push(Conflict { line, path, kind });
+    }
+
+    fn add(&mut self, dir: NodeId, entry: FsEntry) -> NodeId {
+        let index = NodeId(self.nodes.len() as u32);
+        let name = entry.name().to_string();
+        self.nodes.push(entry);
+
+        let dir = self.dir_mut(dir);
+        dir.entries.push(index);
+        dir.children.insert(name, index);
+        index
+    }
+
+    fn cd(&mut self, line: usize, path: &str) {
+        if path.starts_with('/') {
+            self.cwd = Ok(NodeId::ROOT);
+        }
+
+        for segment in path.split('/') {
+            let cwd = match &mut self.cwd {
+                Ok(cwd) => *cwd,
+                // only kept for the warnings about dropped listings
+                Err(lost) => {
+                    if !segment.is_empty() {
+                        lost.push('/');
+                        lost.push_str(segment);
+                    }
+                    continue;
+                }
+            };
+
+            self.cwd = match segment {
+                "" | "." => Ok(cwd),
+                ".." => Ok(self.dir_mut(cwd).parent),
+                name => match find_entry(&self.nodes, cwd, name) {
+                    Some(entry) if matches!(self.nodes[entry.index()], FsEntry::Dir(_)) => {
+                        Ok(entry)
+                    }
+                    Some(_) => {
+                        self.conflict(line, cwd, name, ConflictKind::NotADirectory);
+                        Err(self.entry_path(cwd, name))
+                    }
+                    None => {
+                        let dir = Directory::new(name.to_string(), cwd);
+                        Ok(self.add(cwd, FsEntry::Dir(dir)))
+                    }
+                },
+            };
+        }
+    }
+
+    fn ls(&mut self, content: Vec<(usize, Listing)>) {
+        // the output can not be placed anywhere if the directory is unknown
+        let cwd = match &self.cwd {
+            Ok(cwd) => *cwd,
+            Err(lost) => {
+                let dropped = content
+                    .iter()
+                    .map(|(line, listing)| Conflict {
+                        line: *line,
+                        path: format!("{}/{}", lost, listing.name()),
+                        kind: ConflictKind::Dropped,
+                    })
+                    .collect_vec();
+                self.conflicts.extend(dropped);
+                return;
+            }
+        };
+
+        for (line, listing) in content {
+            let name = listing.name();
+
+            match (find_entry(&self.nodes, cwd, name), &listing) {
+                (None, Listing::Dir(name)) => {
+                    let dir = Directory::new(name.clone(), cwd);
+                    self.add(cwd, FsEntry::Dir(dir));
+                }
+                (None, Listing::File(name, size)) => {
+                    self.add(cwd, FsEntry::File(Fil::new(name.clone(), *size, cwd)));
+                }
+                (Some(entry), _) => match (&self.nodes[entry.index()], &listing) {
+                    (FsEntry::Dir(_), Listing::Dir(_)) => (),
+                    (FsEntry::File(file), Listing::File(_, size)) if file.size == *size => (),
+                    (FsEntry::File(file), Listing::File(name, size)) => {
+                        let kind = ConflictKind::SizeMismatch {
+                            old: file.size,
+                            new: *size,
+                        };
+                        self.conflict(line, cwd, name, kind);
+                    }
+                    _ => self.conflict(line, cwd, name, ConflictKind::KindMismatch),
+                },
+            }
+        }
+
+        self.dir_mut(cwd).listed = true;
     }
 }
 
-fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
-    let file = File::open(name)?;
+impl From<Vec<Command>> for FileSystem {
+    fn from(commands: Vec<Command>) -> Self {
+        let mut builder = Builder::new();
 
-    Ok(io::BufReader::new(file).lines())
+        for cmd in commands {
+            match cmd {
+                Command::CD(line, path) => builder.cd(line, &path),
+                Command::LS(content) => builder.ls(content),
+            }
+        }
+
+        FileSystem {
+            conflicts: builder.conflicts,
+            ..FileSystem::new(builder.nodes)
+        }
+    }
+}
+
+/// A single line of `ls` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Listing {
+    Dir(String),
+    File(String, u64),
+}
+
+impl Listing {
+    fn name(&self) -> &str {
+        match self {
+            Listing::Dir(name) | Listing::File(name, _) => name,
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let (first, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim();
+
+        if name.is_empty() {
+            None
+        } else if first == "dir" {
+            Some(Listing::Dir(name.to_string()))
+        } else {
+            first
+                .parse::<u64>()
+                .ok()
+                .map(|size| Listing::File(name.to_string(), size))
+        }
+    }
+}
+
+/// A command of the transcript, together with the lines it was read from.
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    CD(usize, String),
+    LS(Vec<(usize, Listing)>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseErrorKind {
+    UnknownCommand(String),
+    InvalidArguments(String),
+    OutputWithoutCommand,
+    InvalidListing(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::UnknownCommand(cmd) => write!(f, "unknown command `{cmd}`"),
+            ParseErrorKind::InvalidArguments(cmd) => write!(f, "invalid arguments for `{cmd}`"),
+            ParseErrorKind::OutputWithoutCommand => write!(f, "output without a command"),
+            ParseErrorKind::InvalidListing(line) => write!(f, "invalid `ls` output `{line}`"),
+        }
+    }
+}
+
+/// Parses a terminal transcript line by line.
+///
+/// Only lines starting with `$` are prompts, so names containing `$` are fine. Empty lines are
+/// skipped and flags passed to `ls` are ignored.
+fn parse_transcript(lines: &[String]) -> Result<Vec<Command>, ParseError> {
+    let mut commands = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let error = |kind| ParseError { line: i + 1, kind };
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.strip_prefix('$') {
+            Some(prompt) => match prompt.split_whitespace().collect_vec().as_slice() {
+                ["cd", path] => commands.push(Command::CD(i + 1, path.to_string())),
+                ["ls", flags @ ..] if flags.iter().all(|flag| flag.starts_with('-')) => {
+                    commands.push(Command::LS(Vec::new()))
+                }
+                [cmd @ ("cd" | "ls"), ..] => {
+                    return Err(error(ParseErrorKind::InvalidArguments(cmd.to_string())))
+                }
+                [cmd, ..] => return Err(error(ParseErrorKind::UnknownCommand(cmd.to_string()))),
+                [] => return Err(error(ParseErrorKind::UnknownCommand(String::new()))),
+            },
+            None => match (commands.last_mut(), Listing::parse(line)) {
+                (Some(Command::LS(content)), Some(entry)) => content.push((i + 1, entry)),
+                (Some(Command::LS(_)), None) => {
+                    return Err(error(ParseErrorKind::InvalidListing(line.to_string())))
+                }
+                _ => return Err(error(ParseErrorKind::OutputWithoutCommand)),
+            },
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Matches `name` against a glob pattern supporting `*` and `?`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect_vec();
+    let name = name.chars().collect_vec();
+
+    // matches[j] tells whether the pattern consumed so far matches the first j characters
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+
+    for p in pattern {
+        matches = match p {
+            '*' => matches
+                .iter()
+                .scan(false, |any, m| {
+                    *any |= *m;
+                    Some(*any)
+                })
+                .collect_vec(),
+            p => std::iter::once(false)
+                .chain(
+                    name.iter()
+                        .zip(&matches)
+                        .map(|(c, m)| *m && (p == '?' || p == *c)),
+                )
+                .collect_vec(),
+        };
+    }
+
+    matches[name.len()]
+}
+
+/// Interactive shell over the filesystem reconstructed from the transcript.
+struct Shell<'a> {
+    fs: &'a FileSystem,
+    cwd: NodeId,
+}
+
+impl<'a> Shell<'a> {
+    fn new(fs: &'a FileSystem) -> Self {
+        Self {
+            fs,
+            cwd: NodeId::ROOT,
+        }
+    }
+
+    fn prompt(&self) -> String {
+        format!("{}$ ", self.fs.path(self.cwd))
+    }
+
+    fn lookup(&self, path: Option<&str>) -> Result<NodeId, String> {
+        match path {
+            None => Ok(self.cwd),
+            Some(path) => self
+                .fs
+                .resolve(self.cwd, path)
+                .ok_or(format!("{path}: no such file or directory")),
+        }
+    }
+
+    fn entries(&self, node: NodeId) -> Vec<NodeId> {
+        match &self.fs.nodes[node.index()] {
+            FsEntry::Dir(dir) => dir.entries.clone(),
+            FsEntry::File(_) => vec![node],
+        }
+    }
+
+    fn describe(&self, node: NodeId) -> String {
+        match &self.fs.nodes[node.index()] {
+            FsEntry::Dir(dir) => format!("dir {}", dir.name),
+            FsEntry::File(file) => format!("{} {}", file.size, file.name),
+        }
+    }
+
+    fn find(&self, node: NodeId, pattern: &str, out: &mut Vec<String>) {
+        if glob_match(pattern, self.fs.name(node)) {
+            out.push(self.fs.path(node));
+        }
+        if let FsEntry::Dir(dir) = &self.fs.nodes[node.index()] {
+            for entry in &dir.entries {
+                self.find(*entry, pattern, out);
+            }
+        }
+    }
+
+    /// Executes a single command line, returning its output.
+    fn exec(&mut self, line: &str) -> Result<String, String> {
+        let words = line.split_whitespace().collect_vec();
+
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["pwd"] => Ok(self.fs.path(self.cwd)),
+            ["cd"] => {
+                self.cwd = NodeId::ROOT;
+                Ok(String::new())
+            }
+            ["cd", path] => {
+                let node = self.lookup(Some(path))?;
+                match self.fs.nodes[node.index()] {
+                    FsEntry::Dir(_) => {
+                        self.cwd = node;
+                        Ok(String::new())
+                    }
+                    FsEntry::File(_) => Err(format!("{path}: not a directory")),
+                }
+            }
+            ["ls", rest @ ..] if rest.len() <= 1 => {
+                let node = self.lookup(rest.first().copied())?;
+                Ok(self
+                    .entries(node)
+                    .into_iter()
+                    .map(|entry| self.describe(entry))
+                    .join("\n"))
+            }
+            ["du", rest @ ..] if rest.len() <= 1 => {
+                let node = self.lookup(rest.first().copied())?;
+                let du = Render {
+                    format: Format::Du,
+                    ..Render::new(self.fs, node)
+                };
+                Ok(du.to_string().trim_end().to_string())
+            }
+            ["find", rest @ .., "-name", pattern] if rest.len() <= 1 => {
+                let node = self.lookup(rest.first().copied())?;
+                let mut out = Vec::new();
+                self.find(node, pattern, &mut out);
+                Ok(out.join("\n"))
+            }
+            ["tree", rest @ ..] if rest.len() <= 1 => {
+                let node = self.lookup(rest.first().copied())?;
+                let tree = Render::new(self.fs, node).to_string();
+                Ok(tree.trim_end().to_string())
+            }
+            ["sort", rest @ ..] if rest.len() <= 1 => {
+                let node = self.lookup(rest.first().copied())?;
+                Ok(self
+                    .entries(node)
+                    .into_iter()
+                    .map(|entry| (self.fs.size(entry), entry))
+                    .sorted_by(|a, b| b.0.cmp(&a.0))
+                    .map(|(size, entry)| format!("{size}\t{}", self.fs.name(entry)))
+                    .join("\n"))
+            }
+            ["help"] => Ok(
+                "cd [path], ls [path], pwd, du [path], find [path] -name <pattern>, \
+                 tree [path], sort [path], exit"
+                    .to_string(),
+            ),
+            [cmd, ..] => Err(format!("{cmd}: invalid command or arguments, try `help`")),
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            print!("{}", self.prompt());
+            io::stdout().flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+
+            if matches!(line.trim(), "exit" | "quit") {
+                return Ok(());
+            }
+
+            match self.exec(&line) {
+                Ok(out) if out.is_empty() => (),
+                Ok(out) => println!("{out}"),
+                Err(e) => println!("error: {e}"),
+            }
+        }
+    }
+}
+
+fn parse_fs(lines: &[String]) -> Result<FileSystem, ParseError> {
+    Ok(FileSystem::from(parse_transcript(lines)?))
+}
+
+/// Size of the disk and the free space an update needs.
+#[derive(Debug, Clone, Copy)]
+struct Disk {
+    total: u64,
+    required: u64,
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self {
+            total: 70_000_000,
+            required: 30_000_000,
+        }
+    }
+}
+
+impl Disk {
+    /// Space that still has to be freed when `used` bytes are occupied.
+    fn needed(&self, used: u64) -> u64 {
+        // also right when the tree is larger than the disk it is supposed to be on
+        used.saturating_add(self.required)
+            .saturating_sub(self.total)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    /// The smallest single directory that frees enough space.
+    Single,
+    /// Repeatedly deletes the largest candidate until the smallest one left suffices.
+    Greedy,
+    /// Knapsack over the tree, finds the set that frees the least space that is still enough.
+    Exact,
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Single => write!(f, "single"),
+            Strategy::Greedy => write!(f, "greedy"),
+            Strategy::Exact => write!(f, "exact"),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "single" => Ok(Strategy::Single),
+            "greedy" => Ok(Strategy::Greedy),
+            "exact" => Ok(Strategy::Exact),
+            _ => Err(format!("unknown strategy: {strategy}")),
+        }
+    }
+}
+
+/// Nodes to delete, none of them nested in another.
+#[derive(Debug)]
+struct Plan {
+    nodes: Vec<NodeId>,
+    freed: u64,
+}
+
+// upper bound for the number of partial sums the exact planner combines at once
+const MAX_STATES: usize = 100_000;
+
+/// Chooses what to delete so that the disk has enough free space afterwards.
+struct Planner<'a> {
+    fs: &'a FileSystem,
+    disk: Disk,
+    // whether single files may be deleted, otherwise only whole directories
+    files: bool,
+}
+
+impl<'a> Planner<'a> {
+    fn new(fs: &'a FileSystem, disk: Disk, files: bool) -> Self {
+        Self { fs, disk, files }
+    }
+
+    fn needed(&self) -> u64 {
+        self.disk.needed(self.fs.size(NodeId::ROOT))
+    }
+
+    /// Everything but the root that may be deleted.
+    fn candidates(&self) -> Vec<NodeId> {
+        (1..self.fs.nodes.len() as u32)
+            .map(NodeId)
+            .filter(|node| self.files || matches!(self.fs.nodes[node.index()], FsEntry::Dir(_)))
+            .collect_vec()
+    }
+
+    fn nested(&self, a: NodeId, b: NodeId) -> bool {
+        let is_below = |mut node: NodeId, ancestor: NodeId| loop {
+            if node == ancestor {
+                return true;
+            } else if node == NodeId::ROOT {
+                return false;
+            }
+            node = self.fs.nodes[node.index()].parent();
+        };
+
+        is_below(a, b) || is_below(b, a)
+    }
+
+    fn plan(&self, strategy: Strategy) -> Result<Plan, String> {
+        let needed = self.needed();
+        if needed == 0 {
+            return Ok(Plan {
+                nodes: Vec::new(),
+                freed: 0,
+            });
+        }
+
+        let plan = match strategy {
+            Strategy::Single => self.single(needed),
+            Strategy::Greedy => self.greedy(needed),
+            Strategy::Exact => self.exact(needed)?,
+        };
+
+        plan.ok_or(match strategy {
+            Strategy::Single => format!("no single entry frees {needed}"),
+            _ => format!("deleting everything does not free {needed}"),
+        })
+    }
+
+    fn single(&self, needed: u64) -> Option<Plan> {
+        self.candidates()
+            .into_iter()
+            .filter(|node| self.fs.size(*node) >= needed)
+            .min_by_key(|node| self.fs.size(*node))
+            .map(|node| Plan {
+                nodes: vec![node],
+                freed: self.fs.size(node),
+            })
+    }
+
+    fn greedy(&self, needed: u64) -> Option<Plan> {
+        let mut candidates = self
+            .candidates()
+            .into_iter()
+            .sorted_by_key(|node| std::cmp::Reverse(self.fs.size(*node)))
+            .collect_vec();
+        let mut plan = Plan {
+            nodes: Vec::new(),
+            freed: 0,
+        };
+
+        while plan.freed < needed {
+            let missing = needed - plan.freed;
+            let node = candidates
+                .iter()
+                .rev()
+                .find(|node| self.fs.size(**node) >= missing)
+                .or_else(|| candidates.first())
+                .copied()?;
+
+            plan.nodes.push(node);
+            plan.freed += self.fs.size(node);
+            candidates.retain(|other| !self.nested(*other, node));
+        }
+
+        Some(plan)
+    }
+
+    /// Partial sums reachable below `node`, each with the nodes deleted for it.
+    ///
+    /// Sums that already free enough space are only kept if they are the smallest such sum.
+    fn sums(&self, node: NodeId, needed: u64) -> Result<BTreeMap<u64, Vec<NodeId>>, String> {
+        let mut sums = BTreeMap::from([(0, Vec::new())]);
+
+        if let FsEntry::Dir(dir) = &self.fs.nodes[node.index()] {
+            for entry in &dir.entries {
+                let child = self.sums(*entry, needed)?;
+                if sums.len().saturating_mul(child.len()) > MAX_STATES {
+                    return Err(
+                        "tree is too large for an exact plan, try the greedy one".to_string()
+                    );
+                }
+                let mut combined = BTreeMap::new();
+
+                for ((a, a_nodes), (b, b_nodes)) in sums.iter().cartesian_product(&child) {
+                    combined
+                        .entry(a + b)
+                        .or_insert_with(|| a_nodes.iter().chain(b_nodes).copied().collect_vec());
+                }
+
+                sums = Self::prune(combined, needed);
+            }
+        }
+
+        if node != NodeId::ROOT
+            && (self.files || matches!(self.fs.nodes[node.index()], FsEntry::Dir(_)))
+        {
+            sums.entry(self.fs.size(node)).or_insert_with(|| vec![node]);
+        }
+
+        Ok(Self::prune(sums, needed))
+    }
+
+    fn prune(mut sums: BTreeMap<u64, Vec<NodeId>>, needed: u64) -> BTreeMap<u64, Vec<NodeId>> {
+        let enough = sums.split_off(&needed);
+        sums.extend(enough.into_iter().next());
+        sums
+    }
+
+    fn exact(&self, needed: u64) -> Result<Option<Plan>, String> {
+        Ok(self
+            .sums(NodeId::ROOT, needed)?
+            .range(needed..)
+            .next()
+            .map(|(freed, nodes)| Plan {
+                nodes: nodes.clone(),
+                freed: *freed,
+            }))
+    }
+}
+
+/// A single difference between two snapshots, whole subtrees are reported once.
+#[derive(Debug)]
+enum Change {
+    Added { path: String, dir: bool, size: u64 },
+    Removed { path: String, dir: bool, size: u64 },
+    Resized { path: String, old: u64, new: u64 },
+}
+
+/// Size of a directory that exists in both snapshots but changed.
+#[derive(Debug)]
+struct DirDelta {
+    path: String,
+    old: u64,
+    new: u64,
+}
+
+impl DirDelta {
+    fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+#[derive(Debug, Default)]
+struct FsDiff {
+    changes: Vec<Change>,
+    dirs: Vec<DirDelta>,
+}
+
+impl FsDiff {
+    fn new(old: &FileSystem, new: &FileSystem) -> Self {
+        let mut diff = FsDiff::default();
+        diff.compare(old, new, NodeId::ROOT, NodeId::ROOT);
+        diff
+    }
+
+    fn compare(&mut self, old: &FileSystem, new: &FileSystem, old_dir: NodeId, new_dir: NodeId) {
+        let (old_entries, new_entries) =
+            match (&old.nodes[old_dir.index()], &new.nodes[new_dir.index()]) {
+                (FsEntry::Dir(a), FsEntry::Dir(b)) => (a, b),
+                _ => panic!("only directories can be compared"),
+            };
+
+        for entry in &old_entries.entries {
+            let name = old.name(*entry);
+            let is_dir = matches!(old.nodes[entry.index()], FsEntry::Dir(_));
+
+            match new_entries.children.get(name).copied() {
+                Some(other) => match (&old.nodes[entry.index()], &new.nodes[other.index()]) {
+                    (FsEntry::Dir(_), FsEntry::Dir(_)) => self.compare(old, new, *entry, other),
+                    (FsEntry::File(a), FsEntry::File(b)) if a.size != b.size => {
+                        self.changes.push(Change::Resized {
+                            path: old.path(*entry),
+                            old: a.size,
+                            new: b.size,
+                        })
+                    }
+                    (FsEntry::File(_), FsEntry::File(_)) => (),
+                    // replaced by an entry of the other kind
+                    _ => {
+                        self.changes.push(Change::Removed {
+                            path: old.path(*entry),
+                            dir: is_dir,
+                            size: old.size(*entry),
+                        });
+                        self.changes.push(Change::Added {
+                            path: new.path(other),
+                            dir: !is_dir,
+                            size: new.size(other),
+                        });
+                    }
+                },
+                None => self.changes.push(Change::Removed {
+                    path: old.path(*entry),
+                    dir: is_dir,
+                    size: old.size(*entry),
+                }),
+            }
+        }
+
+        for entry in &new_entries.entries {
+            if !old_entries.children.contains_key(new.name(*entry)) {
+                self.changes.push(Change::Added {
+                    path: new.path(*entry),
+                    dir: matches!(new.nodes[entry.index()], FsEntry::Dir(_)),
+                    size: new.size(*entry),
+                });
+            }
+        }
+
+        if old.size(old_dir) != new.size(new_dir) {
+            self.dirs.push(DirDelta {
+                path: old.path(old_dir),
+                old: old.size(old_dir),
+                new: new.size(new_dir),
+            });
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let entry = |path: &str, dir: bool, size: u64| {
+            let kind = if dir { "dir" } else { "file" };
+            format!(
+                "{{\"path\":{},\"type\":\"{}\",\"size\":{}}}",
+                json_string(path),
+                kind,
+                size
+            )
+        };
+
+        let added = self
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Added { path, dir, size } => Some(entry(path, *dir, *size)),
+                _ => None,
+            })
+            .join(",");
+        let removed = self
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Removed { path, dir, size } => Some(entry(path, *dir, *size)),
+                _ => None,
+            })
+            .join(",");
+        let resized = self
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Resized { path, old, new } => Some(format!(
+                    "{{\"path\":{},\"old\":{},\"new\":{}}}",
+                    json_string(path),
+                    old,
+                    new
+                )),
+                _ => None,
+            })
+            .join(",");
+        let dirs = self
+            .dirs
+            .iter()
+            .map(|dir| {
+                format!(
+                    "{{\"path\":{},\"old\":{},\"new\":{},\"delta\":{}}}",
+                    json_string(&dir.path),
+                    dir.old,
+                    dir.new,
+                    dir.delta()
+                )
+            })
+            .join(",");
+
+        format!(
+            "{{\"added\":[{added}],\"removed\":[{removed}],\"resized\":[{resized}],\"directories\":[{dirs}]}}"
+        )
+    }
+}
+
+impl Display for FsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let suffix = |dir: bool| if dir { "/" } else { "" };
+
+        if self.changes.is_empty() && self.dirs.is_empty() {
+            return writeln!(f, "no differences");
+        }
+
+        for change in &self.changes {
+            match change {
+                Change::Added { path, dir, size } => {
+                    writeln!(f, "+ {}{} ({})", path, suffix(*dir), size)?
+                }
+                Change::Removed { path, dir, size } => {
+                    writeln!(f, "- {}{} ({})", path, suffix(*dir), size)?
+                }
+                Change::Resized { path, old, new } => {
+                    writeln!(f, "~ {} ({} -> {})", path, old, new)?
+                }
+            }
+        }
+
+        if !self.dirs.is_empty() {
+            writeln!(f, "directory sizes:")?;
+        }
+        for dir in &self.dirs {
+            writeln!(
+                f,
+                "  {}: {} -> {} ({:+})",
+                dir.path,
+                dir.old,
+                dir.new,
+                dir.delta()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads and replays a transcript from a file.
+fn load_fs(file_name: &PathBuf) -> Result<FileSystem, String> {
+    let lines = read_lines(file_name)
+        .map_err(|e| format!("{}: {}", file_name.display(), e))?
+        .map_while(Result::ok)
+        .collect_vec();
+
+    let fs = parse_fs(&lines).map_err(|e| format!("{}: {}", file_name.display(), e))?;
+    for conflict in &fs.conflicts {
+        eprintln!("Warning: {}: {}", file_name.display(), conflict);
+    }
+    Ok(fs)
+}
+
+fn task1(fs: &FileSystem) {
+    println!("{}", fs);
+
+    let small = fs
+        .dirs()
+        .filter(|dir| fs.size(*dir) <= 100000)
+        .map(|dir| (fs.path(dir), fs.size(dir)))
+        .sorted()
+        .collect_vec();
+
+    println!(
+        "1. accumulated size of all directories with less than 100000 size: {}",
+        small.iter().map(|(_, size)| size).sum::<u64>()
+    );
+    for (path, size) in small {
+        println!("   {} - {}", path, size);
+    }
+}
+
+fn task2(fs: &FileSystem, disk: Disk) {
+    println!("size of the whole fs: {}", fs.size(NodeId::ROOT));
+
+    match Planner::new(fs, disk, false).plan(Strategy::Single) {
+        Ok(plan) => {
+            println!("2. size of the directory to remove: {}", plan.freed);
+            for node in plan.nodes {
+                println!("   {}", fs.path(node));
+            }
+        }
+        Err(e) => println!("2. no directory to remove: {}", e),
+    }
+}
+
+fn print_plan(fs: &FileSystem, disk: Disk, strategy: Strategy, files: bool) {
+    let planner = Planner::new(fs, disk, files);
+
+    match planner.plan(strategy) {
+        Ok(plan) => {
+            println!(
+                "{} plan: free {} of {} needed by deleting {} entries",
+                strategy,
+                plan.freed,
+                planner.needed(),
+                plan.nodes.len()
+            );
+            for node in plan.nodes {
+                println!("   {} - {}", fs.path(node), fs.size(node));
+            }
+        }
+        Err(e) => println!("{} plan: {}", strategy, e),
+    }
+}
+
+struct Options {
+    shell: bool,
+    export: Option<PathBuf>,
+    transcript: Option<PathBuf>,
+    diff: Option<(PathBuf, PathBuf)>,
+    json: bool,
+    round_trip: bool,
+    lookup: Vec<String>,
+    disk: Disk,
+    plan: Option<Strategy>,
+    files: bool,
+    format: Option<Format>,
+    depth: Option<usize>,
+    sort: SortBy,
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            shell: false,
+            export: None,
+            transcript: None,
+            diff: None,
+            json: false,
+            round_trip: false,
+            lookup: Vec::new(),
+            disk: Disk::default(),
+            plan: None,
+            files: false,
+            format: None,
+            depth: None,
+            sort: SortBy::Listing,
+        };
+
+        fn size(value: Option<String>, arg: &str) -> Result<u64, String> {
+            value
+                .ok_or(format!("{arg} expects a size"))?
+                .parse()
+                .map_err(|e| format!("invalid size for {arg}: {e}"))
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--shell" => options.shell = true,
+                "--round-trip" => options.round_trip = true,
+                "--files" => options.files = true,
+                "--format" => {
+                    options.format = Some(args.next().ok_or("--format expects a format")?.parse()?)
+                }
+                "--sort" => options.sort = args.next().ok_or("--sort expects an order")?.parse()?,
+                "--depth" => {
+                    options.depth = Some(
+                        args.next()
+                            .ok_or("--depth expects a number")?
+                            .parse()
+                            .map_err(|e| format!("invalid depth: {e}"))?,
+                    )
+                }
+                "--disk" => options.disk.total = size(args.next(), "--disk")?,
+                "--required" => options.disk.required = size(args.next(), "--required")?,
+                "--plan" => {
+                    options.plan = Some(args.next().ok_or("--plan expects a strategy")?.parse()?)
+                }
+                "--lookup" => options
+                    .lookup
+                    .push(args.next().ok_or("--lookup expects a path")?),
+                "--export" => {
+                    options.export = Some(PathBuf::from(
+                        args.next().ok_or("--export expects a directory")?,
+                    ))
+                }
+                "--json" => options.json = true,
+                "--diff" => {
+                    let mut file = || {
+                        args.next()
+                            .map(PathBuf::from)
+                            .ok_or("--diff expects two transcripts")
+                    };
+                    options.diff = Some((file()?, file()?))
+                }
+                "--transcript" => {
+                    options.transcript = Some(PathBuf::from(
+                        args.next().ok_or("--transcript expects a directory")?,
+                    ))
+                }
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() {
+    let file_name = PathBuf::from("./input/day7");
+
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => panic!("{e}"),
+    };
+
+    // differences between two snapshots instead of the puzzle input
+    if let Some((old, new)) = &options.diff {
+        match (load_fs(old), load_fs(new)) {
+            (Ok(old), Ok(new)) => {
+                let diff = FsDiff::new(&old, &new);
+                if options.json {
+                    println!("{}", diff.to_json());
+                } else {
+                    print!("{}", diff);
+                }
+            }
+            (Err(e), _) | (_, Err(e)) => println!("Error in transcript: {}", e),
+        }
+        return;
+    }
+
+    // transcript of a real directory instead of the puzzle input
+    if let Some(dir) = &options.transcript {
+        match transcript_from_dir(dir) {
+            Ok(lines) => println!("{}", lines.join("\n")),
+            Err(e) => println!("Error during walking: {}", e),
+        }
+        return;
+    }
+
+    match read_lines(&file_name) {
+        Ok(lines) => {
+            let lines = lines.map_while(Result::ok).collect_vec();
+            let fs = match parse_fs(&lines) {
+                Ok(fs) => fs,
+                Err(e) => {
+                    println!("Error in transcript: {}", e);
+                    return;
+                }
+            };
+
+            // warnings go to stderr so they never end up in machine readable output
+            for conflict in &fs.conflicts {
+                eprintln!("Warning: {}", conflict);
+            }
+
+            let unlisted = fs.unlisted().map(|dir| fs.path(dir)).collect_vec();
+            if !unlisted.is_empty() {
+                eprintln!(
+                    "Warning: contents of {} unknown, sizes are lower bounds",
+                    unlisted.join(", ")
+                );
+            }
+
+            if let Some(dir) = &options.export {
+                match fs.export(dir) {
+                    Ok(()) => println!("exported to {}", dir.display()),
+                    Err(e) => println!("Error during export: {}", e),
+                }
+                return;
+            }
+
+            if !options.lookup.is_empty() {
+                for path in &options.lookup {
+                    match fs.lookup(path) {
+                        Some(node) => println!("{} - {}", fs.path(node), fs.size(node)),
+                        None => println!("{}: no such file or directory", path),
+                    }
+                }
+                return;
+            }
+
+            if options.round_trip {
+                match round_trip(&fs) {
+                    Ok(()) => println!("round trip: ok"),
+                    Err(e) => println!("round trip: {}", e),
+                }
+                return;
+            }
+
+            if let Some(format) = options.format {
+                let render = Render {
+                    format,
+                    depth: options.depth,
+                    sort: options.sort,
+                    ..Render::new(&fs, NodeId::ROOT)
+                };
+                print!("{}", render);
+                return;
+            }
+
+            if options.shell {
+                if let Err(e) = Shell::new(&fs).run() {
+                    println!("Error in shell: {:?}", e);
+                }
+                return;
+            }
+
+            task1(&fs);
+            task2(&fs, options.disk);
+
+            if let Some(strategy) = options.plan {
+                print_plan(&fs, options.disk, strategy, options.files);
+            }
+        }
+        Err(e) => println!("Error during reading: {:?}", e),
+    }
+}
+
+fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
+    let file = File::open(name)?;
+
+    Ok(io::BufReader::new(file).lines())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = "$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("day7-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn example() -> FileSystem {
+        let lines = TRANSCRIPT.lines().map(str::to_string).collect_vec();
+        parse_fs(&lines).expect("valid transcript")
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let fs = example();
+        let dir = temp_dir("round-trip");
+
+        fs.export(&dir).expect("export");
+        let lines = transcript_from_dir(&dir).expect("walk");
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+
+        let copy = parse_fs(&lines).expect("generated transcript");
+        assert_eq!(fs.listing(), copy.listing());
+        assert_eq!(copy.size(NodeId::ROOT), 48381165);
+    }
+
+    #[test]
+    fn export_refuses_non_empty_target() {
+        let dir = temp_dir("non-empty");
+        std::fs::create_dir_all(&dir).expect("create");
+        std::fs::write(dir.join("a"), "keep").expect("write");
+
+        let error = example().export(&dir).expect_err("target is not empty");
+        let kept = std::fs::read_to_string(dir.join("a")).expect("read");
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(kept, "keep");
+    }
+
+    fn transcript(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect_vec()
+    }
+
+    fn parse_error(text: &str) -> ParseError {
+        parse_transcript(&transcript(text)).expect_err("invalid transcript")
+    }
+
+    #[test]
+    fn parses_commands_with_lines() {
+        let commands =
+            parse_transcript(&transcript("$ cd /\n\n$ ls -la\ndir a$\n12 $b c\n$ cd a$"));
+
+        assert_eq!(
+            commands,
+            Ok(vec![
+                Command::CD(1, "/".to_string()),
+                Command::LS(vec![
+                    (4, Listing::Dir("a$".to_string())),
+                    (5, Listing::File("$b c".to_string(), 12)),
+                ]),
+                Command::CD(6, "a$".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert_eq!(
+            parse_error("$ cd /\n$ rm -r a"),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::UnknownCommand("rm".to_string())
+            }
+        );
+        assert_eq!(
+            parse_error("$ ls\n$"),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::UnknownCommand(String::new())
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for (text, cmd) in [("$ cd", "cd"), ("$ cd a b", "cd"), ("$ ls a", "ls")] {
+            assert_eq!(
+                parse_error(text),
+                ParseError {
+                    line: 1,
+                    kind: ParseErrorKind::InvalidArguments(cmd.to_string())
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_output_without_command() {
+        assert_eq!(
+            parse_error("dir a"),
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::OutputWithoutCommand
+            }
+        );
+        assert_eq!(
+            parse_error("$ ls\ndir a\n$ cd a\n12 b"),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::OutputWithoutCommand
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_listings() {
+        for line in ["dir", "12", "x12 a", "-1 a"] {
+            assert_eq!(
+                parse_error(&format!("$ cd /\n$ ls\n{line}")),
+                ParseError {
+                    line: 3,
+                    kind: ParseErrorKind::InvalidListing(line.to_string())
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn changes_into_nested_paths() {
+        let fs = parse_fs(&transcript(
+            "$ cd /a/b\n$ ls\n5 f\n$ cd ../x\n$ ls\n7 g\n$ cd /\n$ ls\ndir a",
+        ))
+        .expect("valid transcript");
+
+        assert_eq!(
+            fs.listing(),
+            vec![
+                ("/a".to_string(), 12),
+                ("/a/b".to_string(), 5),
+                ("/a/b/f".to_string(), 5),
+                ("/a/x".to_string(), 7),
+                ("/a/x/g".to_string(), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn listing_again_is_idempotent() {
+        let fs = example();
+        let mut lines = transcript(TRANSCRIPT);
+        lines.extend(transcript(TRANSCRIPT));
+        let twice = parse_fs(&lines).expect("valid transcript");
+
+        assert!(twice.conflicts.is_empty());
+        assert_eq!(twice.listing(), fs.listing());
+    }
+
+    #[test]
+    fn keeps_first_size_on_conflict() {
+        let fs = parse_fs(&transcript("$ ls\n10 a\n$ ls\n12 a")).expect("valid transcript");
+
+        assert_eq!(
+            fs.conflicts,
+            vec![Conflict {
+                line: 4,
+                path: "/a".to_string(),
+                kind: ConflictKind::SizeMismatch { old: 10, new: 12 }
+            }]
+        );
+        assert_eq!(fs.listing(), vec![("/a".to_string(), 10)]);
+    }
+
+    #[test]
+    fn keeps_first_kind_on_conflict() {
+        let fs = parse_fs(&transcript("$ ls\n10 a\n$ ls\ndir a")).expect("valid transcript");
+
+        assert_eq!(
+            fs.conflicts,
+            vec![Conflict {
+                line: 4,
+                path: "/a".to_string(),
+                kind: ConflictKind::KindMismatch
+            }]
+        );
+        assert_eq!(fs.listing(), vec![("/a".to_string(), 10)]);
+    }
+
+    #[test]
+    fn drops_listings_below_a_file() {
+        let text = "$ ls\n10 a\n$ cd a\n$ cd b\n$ ls\n5 c\ndir d\n$ cd /\n$ ls\n3 e";
+        let fs = parse_fs(&transcript(text)).expect("valid transcript");
+
+        assert_eq!(
+            fs.conflicts,
+            vec![
+                Conflict {
+                    line: 3,
+                    path: "/a".to_string(),
+                    kind: ConflictKind::NotADirectory
+                },
+                Conflict {
+                    line: 6,
+                    path: "/a/b/c".to_string(),
+                    kind: ConflictKind::Dropped
+                },
+                Conflict {
+                    line: 7,
+                    path: "/a/b/d".to_string(),
+                    kind: ConflictKind::Dropped
+                },
+            ]
+        );
+        assert_eq!(
+            fs.conflicts[1].to_string(),
+            "line 6: /a/b/c is dropped, its directory is unknown"
+        );
+        assert_eq!(
+            fs.listing(),
+            vec![("/a".to_string(), 10), ("/e".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn finds_unlisted_directories() {
+        let fs = parse_fs(&transcript("$ cd /a/b\n$ ls\n5 f\n$ cd /\n$ ls\ndir c"))
+            .expect("valid transcript");
+        let unlisted = fs.unlisted().map(|dir| fs.path(dir)).collect_vec();
+
+        assert_eq!(unlisted, vec!["/a".to_string(), "/c".to_string()]);
+        assert_eq!(example().unlisted().count(), 0);
+    }
+
+    fn plan(fs: &FileSystem, disk: Disk, files: bool, strategy: Strategy) -> (Vec<String>, u64) {
+        let plan = Planner::new(fs, disk, files)
+            .plan(strategy)
+            .expect("plan exists");
+        let paths = plan.nodes.iter().map(|node| fs.path(*node)).sorted();
+        (paths.collect_vec(), plan.freed)
+    }
+
+    #[test]
+    fn disk_needed() {
+        let disk = Disk::default();
+        assert_eq!(disk.needed(48381165), 8381165);
+        assert_eq!(disk.needed(10), 0);
+        assert_eq!(disk.needed(u64::MAX), u64::MAX - 70_000_000);
+
+        let disk = Disk {
+            total: 40_000_000,
+            required: u64::MAX,
+        };
+        assert_eq!(disk.needed(48381165), u64::MAX - 40_000_000);
+    }
+
+    #[test]
+    fn single_plan() {
+        let fs = example();
+        let expected = (vec!["/d".to_string()], 24933642);
+
+        assert_eq!(
+            plan(&fs, Disk::default(), false, Strategy::Single),
+            expected
+        );
+        assert_eq!(plan(&fs, Disk::default(), false, Strategy::Exact), expected);
+    }
+
+    #[test]
+    fn greedy_plan() {
+        let disk = Disk {
+            total: 48381165,
+            required: 30_000_000,
+        };
+
+        assert_eq!(
+            plan(&example(), disk, true, Strategy::Greedy),
+            (vec!["/c.dat".to_string(), "/d".to_string()], 33437798)
+        );
+    }
+
+    #[test]
+    fn exact_plan_with_files() {
+        let disk = Disk {
+            total: 70_000_000,
+            required: 40_000_000,
+        };
+
+        assert_eq!(
+            plan(&example(), disk, true, Strategy::Exact),
+            (vec!["/b.txt".to_string(), "/d/j".to_string()], 18908688)
+        );
+    }
+
+    #[test]
+    fn tree_larger_than_disk() {
+        let disk = Disk {
+            total: 40_000_000,
+            required: 0,
+        };
+
+        assert_eq!(
+            plan(&example(), disk, false, Strategy::Single),
+            (vec!["/d".to_string()], 24933642)
+        );
+    }
+
+    #[test]
+    fn nothing_to_free() {
+        let disk = Disk {
+            total: 100_000_000,
+            required: 30_000_000,
+        };
+
+        assert_eq!(
+            plan(&example(), disk, false, Strategy::Exact),
+            (Vec::new(), 0)
+        );
+    }
+
+    #[test]
+    fn no_plan() {
+        let fs = example();
+        // one byte more than the whole tree
+        let disk = Disk {
+            total: 70_000_000,
+            required: 70_000_001,
+        };
+
+        for strategy in [Strategy::Greedy, Strategy::Exact] {
+            assert_eq!(
+                Planner::new(&fs, disk, true).plan(strategy).unwrap_err(),
+                "deleting everything does not free 48381166"
+            );
+        }
+        assert_eq!(
+            Planner::new(&fs, disk, true)
+                .plan(Strategy::Single)
+                .unwrap_err(),
+            "no single entry frees 48381166"
+        );
+    }
+
+    #[test]
+    fn exact_plan_gives_up_on_large_trees() {
+        let mut lines = vec!["$ cd /".to_string(), "$ ls".to_string()];
+        lines.extend((0..20).map(|i| format!("{} f{}", 1u64 << i, i)));
+        let fs = parse_fs(&lines).expect("valid transcript");
+        let disk = Disk {
+            total: 0,
+            required: 1 << 30,
+        };
+
+        let error = Planner::new(&fs, disk, true)
+            .plan(Strategy::Exact)
+            .unwrap_err();
+        assert!(error.contains("too large"), "{error}");
+    }
 }
@@ -1,3 +1,4 @@
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::PathBuf;
@@ -13,149 +14,155 @@ impl Map {
         Self { map }
     }
 
-    // there has to be a better solution but I'm too sleepy rn
-    fn is_visible(&self, x: usize, y: usize) -> bool {
-        let mut visible = true;
-        for dx in 0..x {
-            if self.map[y][dx] >= self.map[y][x] {
-                visible = false;
-            }
-        }
-
-        if visible {
-            return visible;
-        }
-
-        visible = true;
-
-        for dx in x + 1..self.map[y].len() {
-            if self.map[y][dx] >= self.map[y][x] {
-                visible = false;
-            }
-        }
-
-        if visible {
-            return visible;
-        }
+    fn width(&self) -> usize {
+        self.map.first().map_or(0, Vec::len)
+    }
 
-        visible = true;
-        for dy in 0..y {
-            if self.map[dy][x] >= self.map[y][x] {
-                visible = false;
+    /// Visibility of every tree from outside the grid, trees on the edge are always visible.
+    ///
+    /// Sweeps each row and column once in both directions while keeping the highest tree seen
+    /// so far, a tree is visible if it is higher than that running maximum.
+    fn visibility(&self) -> Vec<Vec<bool>> {
+        let (width, height) = (self.width(), self.map.len());
+        let mut visible = vec![vec![false; width]; height];
+
+        let mut sweep = |cells: &mut dyn Iterator<Item = (usize, usize)>| {
+            let mut highest: Option<u8> = None;
+            for (x, y) in cells {
+                if highest.is_none_or(|highest| self.map[y][x] > highest) {
+                    visible[y][x] = true;
+                    highest = Some(self.map[y][x]);
+                }
             }
-        }
+        };
 
-        if visible {
-            return visible;
+        for y in 0..height {
+            sweep(&mut (0..width).map(|x| (x, y)));
+            sweep(&mut (0..width).rev().map(|x| (x, y)));
         }
-
-        visible = true;
-
-        for dy in y + 1..self.map.len() {
-            if self.map[dy][x] >= self.map[y][x] {
-                visible = false;
-            }
+        for x in 0..width {
+            sweep(&mut (0..height).map(|y| (x, y)));
+            sweep(&mut (0..height).rev().map(|y| (x, y)));
         }
 
         visible
     }
 
     fn get_num_visible_trees(&self) -> usize {
-        let mut cnt: usize = 2 * self.map[0].len() + 2 * (self.map.len() - 2);
+        self.visibility().iter().flatten().filter(|v| **v).count()
+    }
 
-        for y in 1..self.map.len() - 1 {
-            for x in 1..self.map[y].len() - 1 {
-                if self.is_visible(x, y) {
-                    cnt += 1;
+    /// Scenic score of every tree, zero for trees on the edge as one viewing distance is zero.
+    ///
+    /// For each line of sight a stack holds the trees that can still block the view, lower
+    /// trees are popped once a higher one is reached, so the viewing distance is the distance
+    /// to the tree left on top of the stack (or to the edge if the stack is empty).
+    fn vis_scores(&self) -> Vec<Vec<usize>> {
+        let (width, height) = (self.width(), self.map.len());
+        let mut scores = vec![vec![1; width]; height];
+
+        let mut sweep = |cells: Vec<(usize, usize)>| {
+            let mut stack: Vec<usize> = Vec::new();
+            for (i, (x, y)) in cells.iter().copied().enumerate() {
+                while stack
+                    .last()
+                    .is_some_and(|top| self.map[cells[*top].1][cells[*top].0] < self.map[y][x])
+                {
+                    stack.pop();
                 }
+                scores[y][x] *= i - stack.last().copied().unwrap_or(0);
+                stack.push(i);
             }
+        };
+
+        for y in 0..height {
+            sweep((0..width).map(|x| (x, y)).collect_vec());
+            sweep((0..width).rev().map(|x| (x, y)).collect_vec());
+        }
+        for x in 0..width {
+            sweep((0..height).map(|y| (x, y)).collect_vec());
+            sweep((0..height).rev().map(|y| (x, y)).collect_vec());
         }
 
-        cnt
+        scores
     }
 
-    fn get_vis_score(&self, x: usize, y: usize) -> usize {
-        if x == 0 || y == 0 || x == self.map[y].len() || y == self.map.len() {
-            0
-        } else {
-            let cur_height = self.map[y][x];
-            let mut dx_l = 1;
-            while dx_l < x && cur_height > self.map[y][x - dx_l] {
-                dx_l += 1;
-            }
-
-            let mut dx_r = 1;
-            while dx_r + x < self.map[y].len()-1 && cur_height > self.map[y][x + dx_r] {
-                dx_r += 1;
-            }
-
-            let mut dy_u = 1;
-            while dy_u < y && cur_height > self.map[y - dy_u][x] {
-                dy_u += 1;
-            }
-
-            let mut dy_d = 1;
-            while dy_d + y < self.map.len()-1 && cur_height > self.map[y + dy_d][x] {
-                dy_d += 1;
-            }
-
-            println!("{dx_l} * {dx_r} * {dy_u} * {dy_d}");
-            dx_l * dx_r * dy_u * dy_d
-        }
+    fn get_max_vis_score(&self) -> usize {
+        self.vis_scores()
+            .into_iter()
+            .flatten()
+            .max()
+            .unwrap_or_default()
     }
+}
 
-    fn get_max_vis_score(&self) -> usize {
-        let mut max_score: usize = usize::MIN;
-
-        for y in 1..self.map.len() - 1 {
-            for x in 1..self.map[y].len() - 1 {
-                let cur_score = self.get_vis_score(x, y);
-                if max_score < cur_score {
-                    println!("updating max score: {x} {y} = {}", self.map[y][x]);
-                    max_score = cur_score;
-                }
+#[derive(Debug, PartialEq, Eq)]
+enum MapError {
+    InvalidDigit {
+        line: usize,
+        tree: char,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::InvalidDigit { line, tree } => {
+                write!(f, "line {line}: invalid tree height '{tree}'")
             }
+            MapError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(f, "line {line}: expected {expected} trees, found {found}"),
         }
-
-        max_score
     }
 }
 
-impl From<Vec<String>> for Map {
-    fn from(input: Vec<String>) -> Self {
+impl TryFrom<Vec<String>> for Map {
+    type Error = MapError;
+
+    fn try_from(input: Vec<String>) -> Result<Self, Self::Error> {
         let mut map: Vec<Vec<u8>> = Vec::new();
 
-        input.iter().for_each(|line| {
-            let mut row: Vec<u8> = Vec::new();
-            line.chars().for_each(|tree| {
-                row.push(match tree.to_digit(10) {
-                    Some(digit) => digit as u8,
-                    None => panic!("error converting digit {tree}"),
+        for (i, line) in input.iter().enumerate() {
+            let row = line
+                .chars()
+                .map(|tree| match tree.to_digit(10) {
+                    Some(digit) => Ok(digit as u8),
+                    None => Err(MapError::InvalidDigit { line: i + 1, tree }),
                 })
-            });
-            map.push(row)
-        });
+                .collect::<Result<Vec<u8>, _>>()?;
+
+            // all rows have to be as wide as the first one
+            if let Some(first) = map.first().filter(|first| first.len() != row.len()) {
+                return Err(MapError::RaggedRow {
+                    line: i + 1,
+                    expected: first.len(),
+                    found: row.len(),
+                });
+            }
+            map.push(row);
+        }
 
-        Map::new(map)
+        Ok(Map::new(map))
     }
 }
 
-fn task1(lines: Vec<String>) {
-    let map = Map::from(lines);
-
+fn task1(map: &Map) {
     println!(
         "1. number of visible trees: {}",
         map.get_num_visible_trees()
     );
 }
 
-fn task2(lines: Vec<String>) {
-    let map = Map::from(lines);
-
-    println!(
-        "2. maximum visibility score: {}",
-        map.get_max_vis_score()
-    );
+fn task2(map: &Map) {
+    println!("2. maximum visibility score: {}", map.get_max_vis_score());
 }
 
 fn main() {
@@ -163,9 +170,14 @@ fn main() {
 
     match read_lines(&file_name) {
         Ok(lines) => {
-            let lines = lines.flatten().collect_vec();
-            task1(lines.clone());
-            task2(lines);
+            let lines = lines.map_while(Result::ok).collect_vec();
+            match Map::try_from(lines) {
+                Ok(map) => {
+                    task1(&map);
+                    task2(&map);
+                }
+                Err(e) => println!("Error in input: {}", e),
+            }
         }
         Err(e) => println!("Error during reading: {:?}", e),
     }
@@ -176,3 +188,125 @@ fn read_lines(name: &PathBuf) -> io::Result<io::Lines<io::BufReader<File>>> {
 
     Ok(io::BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(rows: &[&str]) -> Result<Map, MapError> {
+        Map::try_from(rows.iter().map(|row| row.to_string()).collect_vec())
+    }
+
+    const EXAMPLE: [&str; 5] = ["30373", "25512", "65332", "33549", "35390"];
+
+    /// Trees seen from `(x, y)` in the four directions, nearest first.
+    fn lines_of_sight(map: &Map, x: usize, y: usize) -> [Vec<u8>; 4] {
+        let row = &map.map[y];
+        let column = map.map.iter().map(|row| row[x]).collect_vec();
+
+        [
+            row[..x].iter().rev().copied().collect(),
+            row[x + 1..].to_vec(),
+            column[..y].iter().rev().copied().collect(),
+            column[y + 1..].to_vec(),
+        ]
+    }
+
+    /// Visibility and scenic scores straight from the definition.
+    fn brute_force(map: &Map) -> (Vec<Vec<bool>>, Vec<Vec<usize>>) {
+        let (width, height) = (map.width(), map.map.len());
+        let mut visible = vec![vec![false; width]; height];
+        let mut scores = vec![vec![0; width]; height];
+
+        for (y, x) in (0..height).cartesian_product(0..width) {
+            let tree = map.map[y][x];
+            let sights = lines_of_sight(map, x, y);
+
+            visible[y][x] = sights.iter().any(|sight| sight.iter().all(|t| *t < tree));
+            scores[y][x] = sights
+                .iter()
+                .map(|sight| match sight.iter().position(|t| *t >= tree) {
+                    Some(blocked) => blocked + 1,
+                    None => sight.len(),
+                })
+                .product();
+        }
+
+        (visible, scores)
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let mut maps = vec![map(&EXAMPLE).expect("valid map")];
+        for _ in 0..20 {
+            let (width, height) = (random() % 8 + 1, random() % 8 + 1);
+            // few heights, so that equal trees block each other often
+            let rows = (0..height)
+                .map(|_| (0..width).map(|_| (random() % 4) as u8).collect_vec())
+                .collect_vec();
+            maps.push(Map::new(rows));
+        }
+
+        for map in maps {
+            let (visible, scores) = brute_force(&map);
+            assert_eq!(map.visibility(), visible, "{:?}", map.map);
+            assert_eq!(map.vis_scores(), scores, "{:?}", map.map);
+        }
+    }
+
+    #[test]
+    fn example() {
+        let map = map(&EXAMPLE).expect("valid map");
+
+        assert_eq!(map.get_num_visible_trees(), 21);
+        assert_eq!(map.get_max_vis_score(), 8);
+    }
+
+    #[test]
+    fn single_row() {
+        let map = map(&["3037"]).expect("valid map");
+
+        assert_eq!(map.get_num_visible_trees(), 4);
+        assert_eq!(map.get_max_vis_score(), 0);
+    }
+
+    #[test]
+    fn single_column() {
+        let map = map(&["3", "0", "3", "7"]).expect("valid map");
+
+        assert_eq!(map.get_num_visible_trees(), 4);
+        assert_eq!(map.get_max_vis_score(), 0);
+    }
+
+    #[test]
+    fn empty() {
+        let map = map(&[]).expect("valid map");
+
+        assert_eq!(map.get_num_visible_trees(), 0);
+        assert_eq!(map.get_max_vis_score(), 0);
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        assert_eq!(
+            map(&["303", "25"]).err(),
+            Some(MapError::RaggedRow {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            map(&["303", "2x5"]).err(),
+            Some(MapError::InvalidDigit { line: 2, tree: 'x' })
+        );
+    }
+}